@@ -0,0 +1,98 @@
+//! Per-account activity timestamps.
+//!
+//! The contract records when each account last sent and last received tokens, for
+//! inactivity-based features and basic analytics. The record is part of the account's
+//! registration, so the minimum storage balance covers it. The contract's own account, which
+//! only holds tokens in escrow, is not tracked.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U64;
+use near_sdk::{env, near_bindgen, AccountId};
+
+use crate::types::AccountActivityView;
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Default)]
+pub struct AccountActivity {
+    /// Block timestamp (in nanoseconds) of the last outgoing transfer, or 0 if none.
+    pub last_sent_at: u64,
+    /// Block timestamp (in nanoseconds) of the last incoming transfer, or 0 if none.
+    pub last_received_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns when `account_id` last sent and received tokens, or `None` if it is not
+    /// registered.
+    pub fn get_account_activity(&self, account_id: AccountId) -> Option<AccountActivityView> {
+        if !self.token.accounts.contains_key(&account_id) {
+            return None;
+        }
+        let activity = self.account_activity.get(&account_id).unwrap_or_default();
+        let timestamp = |t: u64| if t == 0 { None } else { Some(U64(t)) };
+        Some(AccountActivityView {
+            last_sent_at: timestamp(activity.last_sent_at),
+            last_received_at: timestamp(activity.last_received_at),
+        })
+    }
+}
+
+impl Contract {
+    /// Adds the storage of an activity record to the storage paid on registration.
+    pub(crate) fn measure_activity_storage_usage(&mut self) {
+        let initial_storage_usage = env::storage_usage();
+        let tmp_account_id = AccountId::new_unchecked("a".repeat(64));
+        self.account_activity.insert(&tmp_account_id, &AccountActivity::default());
+        self.token.account_storage_usage += env::storage_usage() - initial_storage_usage;
+        self.account_activity.remove(&tmp_account_id);
+    }
+
+    pub(crate) fn internal_record_activity(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) {
+        let now = env::block_timestamp();
+        let contract_id = env::current_account_id();
+        if sender_id != &contract_id {
+            let mut activity = self.account_activity.get(sender_id).unwrap_or_default();
+            activity.last_sent_at = now;
+            self.account_activity.insert(sender_id, &activity);
+        }
+        if receiver_id != &contract_id {
+            let mut activity = self.account_activity.get(receiver_id).unwrap_or_default();
+            activity.last_received_at = now;
+            self.account_activity.insert(receiver_id, &activity);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::setup_contract;
+
+    #[test]
+    fn test_account_activity() {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        let activity = contract.get_account_activity(accounts(1)).unwrap();
+        assert!(activity.last_received_at.is_none());
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .block_timestamp(1_000)
+            .build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        let sender = contract.get_account_activity(accounts(2)).unwrap();
+        let receiver = contract.get_account_activity(accounts(1)).unwrap();
+        assert_eq!(sender.last_sent_at, Some(U64(1_000)));
+        assert_eq!(receiver.last_received_at, Some(U64(1_000)));
+        assert!(contract.get_account_activity(accounts(3)).is_none());
+    }
+}
@@ -0,0 +1,140 @@
+//! Owner batch airdrops.
+//!
+//! `airdrop` sends tokens from the owner's balance to many accounts in one transaction,
+//! registering the receivers that aren't registered yet with the owner's attached deposit.
+//! Airdrops aren't charged the transfer fee. All the transfers credited right away are reported
+//! in a single `ft_transfer` event. The batch length is capped so a call stays within the gas
+//! limit.
+
+use near_contract_standards::fungible_token::events::FtTransfer;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::pause::Feature;
+use crate::{Contract, ContractExt};
+
+/// Maximum number of receivers in one airdrop.
+const MAX_AIRDROP_BATCH: usize = 100;
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers each amount from the owner to its account, registering unregistered accounts.
+    /// Each transfer goes through the same checks as `ft_transfer`, but isn't charged the transfer
+    /// fee, and is held for confirmation if its receiver requires it. Requires at least 1
+    /// yoctoNEAR attached; the attached deposit pays for the registrations and the held
+    /// transfers, and the rest is refunded.
+    #[payable]
+    pub fn airdrop(&mut self, transfers: Vec<(AccountId, U128)>) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        self.assert_owner();
+        require!(!transfers.is_empty(), "The airdrop has no receivers");
+        require!(transfers.len() <= MAX_AIRDROP_BATCH, "Too many receivers in one airdrop");
+        let owner_id = self.owner_id.clone();
+        let memo = Some("airdrop".to_string());
+        let storage_cost: Balance = self.token.storage_balance_bounds().min.into();
+        let mut registrations_cost = 0;
+        let mut pending_storage = 0;
+        let mut credited = Vec::with_capacity(transfers.len());
+        for (account_id, amount) in transfers.iter() {
+            require!(amount.0 > 0, "The amount should be a positive number");
+            require!(*account_id != owner_id, "The owner can't airdrop to itself");
+            if !self.token.accounts.contains_key(account_id) {
+                self.assert_not_paused(Feature::Storage);
+                self.token.internal_register_account(account_id);
+                registrations_cost += storage_cost;
+            }
+            self.internal_check_transfer(&owner_id, account_id, amount.0);
+            match self.internal_hold_for_confirmation(&owner_id, account_id, amount.0, &memo) {
+                Ok(amount) => {
+                    self.token.internal_withdraw(&owner_id, amount);
+                    self.token.internal_deposit(account_id, amount);
+                    self.internal_finish_transfer(&owner_id, account_id, amount, memo.clone());
+                    credited.push((account_id, U128(amount)));
+                }
+                Err(storage) => pending_storage += storage,
+            }
+        }
+
+        let deposit = env::attached_deposit();
+        let storage_deposit =
+            registrations_cost + env::storage_byte_cost() * Balance::from(pending_storage);
+        require!(
+            deposit >= storage_deposit,
+            format!("Must attach {} yoctoNEAR to cover the storage", storage_deposit)
+        );
+        let refund = deposit - storage_deposit;
+        if refund > 0 {
+            Promise::new(owner_id.clone()).transfer(refund);
+        }
+        if credited.is_empty() {
+            return;
+        }
+        let events: Vec<FtTransfer> = credited
+            .iter()
+            .map(|(account_id, amount)| FtTransfer {
+                old_owner_id: &owner_id,
+                new_owner_id: account_id,
+                amount,
+                memo: Some("airdrop"),
+            })
+            .collect();
+        FtTransfer::emit_many(&events);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, TOTAL_SUPPLY};
+
+    #[test]
+    fn test_airdrop() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(2 * storage_cost)
+            .build());
+        contract.airdrop(vec![(accounts(1), 100.into()), (accounts(3), 200.into())]);
+
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 300);
+        let logs = get_logs();
+        assert_eq!(logs.len(), 1);
+        assert!(logs[0].contains(r#""event":"ft_transfer""#));
+    }
+
+    #[test]
+    #[should_panic(expected = "to cover the storage")]
+    fn test_airdrop_without_storage_deposit() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.airdrop(vec![(accounts(1), 100.into())]);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+    fn test_airdrop_without_deposit() {
+        let (_, mut contract) = setup_contract(&[]);
+        contract.airdrop(vec![(accounts(1), 100.into())]);
+    }
+
+    #[test]
+    #[should_panic(expected = "The receiver doesn't accept transfers from this sender")]
+    fn test_airdrop_outside_inbound_allowlist() {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_inbound_allowlist(Some(vec![accounts(3)]));
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.airdrop(vec![(accounts(1), 100.into())]);
+    }
+}
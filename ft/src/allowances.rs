@@ -0,0 +1,419 @@
+//! Allowances for pull-based transfers.
+//!
+//! An owner can allow a spender to move up to a given amount of its tokens with
+//! `ft_transfer_from`, which uses the allowance up. Operators with an unlimited approval (see
+//! `operators`) can call `ft_transfer_from` without an allowance. Every change of an allowance
+//! emits an `ft_approval` event with the new amount. The owner's attached deposit pays for
+//! storing new allowances, and the storage is refunded to the owner once an allowance drops to
+//! zero. The allowances are indexed both by owner and by spender, so they can be listed with
+//! `allowances_of_owner` and `allowances_of_spender`.
+//!
+//! An allowance can expire at a given block timestamp. Expired allowances can't be used, and
+//! anyone can purge them with `purge_expired_allowances`, which refunds the storage to the owners.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::FtApproval;
+use crate::types::AllowanceView;
+use crate::utils::{refund_deposit, refund_released_storage, refund_storage_change};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of allowances purged in one call.
+const MAX_PURGE: usize = 100;
+/// Maximum number of spenders an owner can have an allowance for at a time.
+const MAX_SPENDERS: usize = 50;
+/// Maximum number of owners a spender can have an allowance from at a time.
+const MAX_OWNERS: usize = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct Allowance {
+    pub amount: Balance,
+    /// Block timestamp (in nanoseconds) from which the allowance can't be used.
+    pub expires_at: Option<u64>,
+}
+
+impl Allowance {
+    fn is_expired(&self) -> bool {
+        self.expires_at.map_or(false, |expires_at| env::block_timestamp() >= expires_at)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the allowance of `spender_id` over the caller's tokens to `amount`, optionally
+    /// expiring at the `expires_at` block timestamp (in nanoseconds). Requires at least 1
+    /// yoctoNEAR attached, plus the storage cost of a new allowance.
+    #[payable]
+    pub fn ft_approve(&mut self, spender_id: AccountId, amount: U128, expires_at: Option<U64>) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        let owner_id = env::predecessor_account_id();
+        require!(owner_id != spender_id, "The owner can't be its own spender");
+        self.token.internal_unwrap_balance_of(&owner_id);
+        let expires_at = expires_at.map(|expires_at| expires_at.0);
+        if let Some(expires_at) = expires_at {
+            require!(expires_at > env::block_timestamp(), "The expiry must be in the future");
+        }
+        self.internal_set_allowance(
+            owner_id,
+            spender_id,
+            Allowance { amount: amount.0, expires_at },
+        );
+    }
+
+    /// Raises the caller's allowance for `spender_id`, keeping its expiry. An expired allowance
+    /// can't be raised; set a new one with `ft_approve` instead. Requires at least 1 yoctoNEAR
+    /// attached.
+    #[payable]
+    pub fn ft_increase_allowance(&mut self, spender_id: AccountId, added_amount: U128) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        let owner_id = env::predecessor_account_id();
+        require!(owner_id != spender_id, "The owner can't be its own spender");
+        self.token.internal_unwrap_balance_of(&owner_id);
+        let mut allowance =
+            self.allowances.get(&(owner_id.clone(), spender_id.clone())).unwrap_or_default();
+        require!(!allowance.is_expired(), "The allowance has expired");
+        allowance.amount = allowance
+            .amount
+            .checked_add(added_amount.into())
+            .unwrap_or_else(|| env::panic_str("Allowance overflow"));
+        self.internal_set_allowance(owner_id, spender_id, allowance);
+    }
+
+    /// Lowers the caller's allowance for `spender_id`, keeping its expiry.
+    #[payable]
+    pub fn ft_decrease_allowance(&mut self, spender_id: AccountId, subtracted_amount: U128) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let mut allowance = self.internal_unexpired_allowance(&owner_id, &spender_id);
+        allowance.amount = allowance
+            .amount
+            .checked_sub(subtracted_amount.into())
+            .unwrap_or_else(|| env::panic_str("The allowance would drop below zero"));
+        self.internal_set_allowance(owner_id, spender_id, allowance);
+    }
+
+    /// Returns the amount `spender_id` can still move, which is zero once the allowance expired.
+    pub fn ft_allowance(&self, owner_id: AccountId, spender_id: AccountId) -> U128 {
+        self.internal_unexpired_allowance(&owner_id, &spender_id).amount.into()
+    }
+
+    /// Returns the block timestamp at which the allowance expires, if it has an expiry.
+    pub fn ft_allowance_expires_at(
+        &self,
+        owner_id: AccountId,
+        spender_id: AccountId,
+    ) -> Option<U64> {
+        self.allowances
+            .get(&(owner_id, spender_id))
+            .and_then(|allowance| allowance.expires_at)
+            .map(U64)
+    }
+
+    /// Returns up to `limit` of the allowances `owner_id` gave, starting at `from_index`. Expired
+    /// allowances are left out.
+    pub fn allowances_of_owner(
+        &self,
+        owner_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<AllowanceView> {
+        self.allowance_spenders
+            .get(&owner_id)
+            .unwrap_or_default()
+            .into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(MAX_SPENDERS, |limit| limit as usize))
+            .filter_map(|spender_id| self.internal_allowance_view(owner_id.clone(), spender_id))
+            .collect()
+    }
+
+    /// Returns up to `limit` of the allowances `spender_id` was given, starting at `from_index`.
+    /// Expired allowances are left out.
+    pub fn allowances_of_spender(
+        &self,
+        spender_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<AllowanceView> {
+        self.allowance_owners
+            .get(&spender_id)
+            .unwrap_or_default()
+            .into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(MAX_OWNERS, |limit| limit as usize))
+            .filter_map(|owner_id| self.internal_allowance_view(owner_id, spender_id.clone()))
+            .collect()
+    }
+
+    /// Transfers `amount` of `owner_id`'s tokens to `receiver_id` on behalf of the owner, using
+    /// up the caller's allowance. Requires at least 1 yoctoNEAR attached, plus the storage cost
+    /// of the transfer if the receiver requires confirmed transfers.
+    #[payable]
+    pub fn ft_transfer_from(
+        &mut self,
+        owner_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(
+            !self.approval_freezes.contains_key(&owner_id),
+            "The owner's approvals are frozen"
+        );
+        let spender_id = env::predecessor_account_id();
+        require!(
+            !self.internal_exceeds_soft_limit(&owner_id, amount.into()),
+            "The amount exceeds the owner's soft limit"
+        );
+        if !self.internal_is_unlimited_operator(&owner_id, &spender_id) {
+            let mut allowance =
+                self.allowances.get(&(owner_id.clone(), spender_id.clone())).unwrap_or_default();
+            require!(!allowance.is_expired(), "The allowance has expired");
+            require!(allowance.amount >= amount.0, "The amount exceeds the allowance");
+            allowance.amount -= amount.0;
+            let initial_storage_usage = env::storage_usage();
+            self.internal_write_allowance(owner_id.clone(), spender_id, allowance);
+            refund_released_storage(
+                owner_id.clone(),
+                initial_storage_usage.saturating_sub(env::storage_usage()),
+            );
+        }
+        refund_deposit(self.internal_transfer(&owner_id, &receiver_id, amount.into(), memo));
+    }
+
+    /// Removes the expired allowances among the given (owner, spender) pairs and refunds their
+    /// storage to the owners. Pairs without an expired allowance are skipped. Returns the number
+    /// of removed allowances.
+    pub fn purge_expired_allowances(&mut self, allowances: Vec<(AccountId, AccountId)>) -> u32 {
+        require!(allowances.len() <= MAX_PURGE, "Too many allowances to purge at once");
+        let mut purged = 0;
+        for (owner_id, spender_id) in allowances {
+            let allowance = self.allowances.get(&(owner_id.clone(), spender_id.clone()));
+            if !allowance.map_or(false, |allowance| allowance.is_expired()) {
+                continue;
+            }
+            self.internal_set_allowance(owner_id, spender_id, Allowance::default());
+            purged += 1;
+        }
+        purged
+    }
+}
+
+impl Contract {
+    /// Removes all the allowances of `owner_id`, without refunding their storage.
+    pub(crate) fn internal_remove_allowances(&mut self, owner_id: &AccountId) {
+        for spender_id in self.allowance_spenders.remove(owner_id).unwrap_or_default() {
+            self.internal_remove_allowance_owner(&spender_id, owner_id);
+            self.allowances.remove(&(owner_id.clone(), spender_id));
+        }
+    }
+
+    fn internal_remove_allowance_owner(&mut self, spender_id: &AccountId, owner_id: &AccountId) {
+        let mut owners = self.allowance_owners.get(spender_id).unwrap_or_default();
+        owners.retain(|account_id| account_id != owner_id);
+        if owners.is_empty() {
+            self.allowance_owners.remove(spender_id);
+        } else {
+            self.allowance_owners.insert(spender_id, &owners);
+        }
+    }
+
+    fn internal_allowance_view(
+        &self,
+        owner_id: AccountId,
+        spender_id: AccountId,
+    ) -> Option<AllowanceView> {
+        let allowance = self.allowances.get(&(owner_id.clone(), spender_id.clone()))?;
+        if allowance.is_expired() {
+            return None;
+        }
+        Some(AllowanceView {
+            owner_id,
+            spender_id,
+            amount: allowance.amount.into(),
+            expires_at: allowance.expires_at.map(U64),
+        })
+    }
+
+    /// Returns the allowance of `spender_id`, or an empty one if it expired.
+    fn internal_unexpired_allowance(
+        &self,
+        owner_id: &AccountId,
+        spender_id: &AccountId,
+    ) -> Allowance {
+        self.allowances
+            .get(&(owner_id.clone(), spender_id.clone()))
+            .filter(|allowance| !allowance.is_expired())
+            .unwrap_or_default()
+    }
+
+    /// Stores the allowance, charging the caller for a new entry or refunding the owner for a
+    /// removed one, and refunds the rest of the attached deposit to the caller.
+    pub(crate) fn internal_set_allowance(
+        &mut self,
+        owner_id: AccountId,
+        spender_id: AccountId,
+        allowance: Allowance,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.internal_write_allowance(owner_id.clone(), spender_id, allowance);
+        refund_storage_change(owner_id, initial_storage_usage);
+    }
+
+    /// Stores the allowance, keeping the owner's spender list and the spender's owner list in
+    /// sync, and emits `ft_approval`.
+    /// The storage change is left to the caller to settle.
+    fn internal_write_allowance(
+        &mut self,
+        owner_id: AccountId,
+        spender_id: AccountId,
+        allowance: Allowance,
+    ) {
+        let key = (owner_id, spender_id);
+        let mut spenders = self.allowance_spenders.get(&key.0).unwrap_or_default();
+        if allowance.amount == 0 {
+            if self.allowances.remove(&key).is_some() {
+                spenders.retain(|spender_id| *spender_id != key.1);
+                self.internal_remove_allowance_owner(&key.1, &key.0);
+            }
+        } else if self.allowances.insert(&key, &allowance).is_none() {
+            require!(spenders.len() < MAX_SPENDERS, "Too many allowances");
+            spenders.push(key.1.clone());
+            let mut owners = self.allowance_owners.get(&key.1).unwrap_or_default();
+            require!(owners.len() < MAX_OWNERS, "The spender has too many allowances");
+            owners.push(key.0.clone());
+            self.allowance_owners.insert(&key.1, &owners);
+        }
+        if spenders.is_empty() {
+            self.allowance_spenders.remove(&key.0);
+        } else {
+            self.allowance_spenders.insert(&key.0, &spenders);
+        }
+        let (owner_id, spender_id) = key;
+        FtApproval {
+            owner_id: &owner_id,
+            spender_id: &spender_id,
+            amount: &U128(allowance.amount),
+            expires_at: allowance.expires_at.map(U64).as_ref(),
+        }
+        .emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Allows accounts(3) to spend 300 of the tokens of accounts(2).
+    fn setup(expires_at: Option<U64>) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.ft_approve(accounts(3), 300.into(), expires_at);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(3))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_from_uses_allowance() {
+        let (_, mut contract) = setup(None);
+        contract.ft_transfer_from(accounts(2), accounts(0), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 100);
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, 200);
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the allowance")]
+    fn test_transfer_from_above_allowance() {
+        let (_, mut contract) = setup(None);
+        contract.ft_transfer_from(accounts(2), accounts(0), 301.into(), None);
+    }
+
+    #[test]
+    fn test_increase_and_decrease_allowance() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_increase_allowance(accounts(3), 200.into());
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, 500);
+        contract.ft_decrease_allowance(accounts(3), 500.into());
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The allowance has expired")]
+    fn test_transfer_from_with_expired_allowance() {
+        let (mut context, mut contract) = setup(Some(1_000.into()));
+        testing_env!(context.block_timestamp(1_000).build());
+        contract.ft_transfer_from(accounts(2), accounts(0), 100.into(), None);
+    }
+
+    #[test]
+    fn test_purge_expired_allowances() {
+        let (mut context, mut contract) = setup(Some(1_000.into()));
+        let pair = (accounts(2), accounts(3));
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(4)).build());
+        assert_eq!(contract.purge_expired_allowances(vec![pair.clone()]), 0);
+        testing_env!(context.block_timestamp(1_000).build());
+        assert_eq!(contract.purge_expired_allowances(vec![pair]), 1);
+        assert!(contract.ft_allowance_expires_at(accounts(2), accounts(3)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The allowance has expired")]
+    fn test_increase_expired_allowance() {
+        let (mut context, mut contract) = setup(Some(1_000.into()));
+        testing_env!(context.block_timestamp(1_000).predecessor_account_id(accounts(2)).build());
+        contract.ft_increase_allowance(accounts(3), 100.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+    fn test_approve_without_deposit() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(2)).build());
+        contract.ft_approve(accounts(3), u128::MAX.into(), None);
+    }
+
+    #[test]
+    fn test_allowances_of_owner_and_spender() {
+        let (mut context, mut contract) = setup(Some(1_000.into()));
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_approve(accounts(4), 100.into(), None);
+        let owned = contract.allowances_of_owner(accounts(2), None, None);
+        assert_eq!(owned.len(), 2);
+        assert_eq!((owned[1].spender_id.clone(), owned[1].amount.0), (accounts(4), 100));
+        assert_eq!(contract.allowances_of_owner(accounts(2), Some(1), Some(1)).len(), 1);
+        let given = contract.allowances_of_spender(accounts(3), None, None);
+        assert_eq!((given[0].owner_id.clone(), given[0].amount.0), (accounts(2), 300));
+        assert_eq!(given[0].expires_at, Some(1_000.into()));
+        testing_env!(context.block_timestamp(1_000).attached_deposit(1).build());
+        assert!(contract.allowances_of_spender(accounts(3), None, None).is_empty());
+        contract.ft_decrease_allowance(accounts(4), 100.into());
+        assert!(contract.allowances_of_owner(accounts(2), None, None).is_empty());
+        assert!(contract.allowances_of_spender(accounts(4), None, None).is_empty());
+    }
+
+    #[test]
+    fn test_update_allowance_refunds_deposit() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context.attached_deposit(1_000).predecessor_account_id(accounts(2)).build());
+        contract.ft_approve(accounts(3), 400.into(), None);
+        let refund = get_created_receipts().pop().unwrap();
+        assert_eq!(refund.receiver_id, accounts(2));
+        assert!(matches!(refund.actions[..], [VmAction::Transfer { deposit: 1_000 }]));
+    }
+}
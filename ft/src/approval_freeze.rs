@@ -0,0 +1,106 @@
+//! Emergency freeze of an account's approvals.
+//!
+//! An owner that suspects a malicious approval can freeze all of its approvals at once with
+//! `freeze_approvals`. The approvals are kept but can't be used until the owner unfreezes them,
+//! which takes a request and `UNFREEZE_DELAY` of waiting, so that an attacker who gets hold of the
+//! owner's key can't just undo the freeze and drain the account through its operators.
+
+use near_sdk::json_types::U64;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::types::ApprovalFreeze;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Time between an unfreeze request and the unfreeze, in nanoseconds.
+const UNFREEZE_DELAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[near_bindgen]
+impl Contract {
+    /// Suspends every approval of the caller. Also cancels a pending unfreeze request. The
+    /// attached deposit pays for storing the freeze.
+    #[payable]
+    pub fn freeze_approvals(&mut self) {
+        let owner_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&owner_id);
+        let initial_storage_usage = env::storage_usage();
+        self.approval_freezes.insert(&owner_id, &None);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Starts the delay after which the caller can unfreeze its approvals.
+    #[payable]
+    pub fn request_unfreeze_approvals(&mut self) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        require!(self.approval_freezes.contains_key(&owner_id), "The approvals are not frozen");
+        self.approval_freezes.insert(&owner_id, &Some(env::block_timestamp() + UNFREEZE_DELAY));
+    }
+
+    /// Restores the caller's approvals once the unfreeze delay has passed.
+    #[payable]
+    pub fn unfreeze_approvals(&mut self) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let unfreezes_at = self
+            .approval_freezes
+            .get(&owner_id)
+            .unwrap_or_else(|| env::panic_str("The approvals are not frozen"))
+            .unwrap_or_else(|| env::panic_str("Request the unfreeze first"));
+        require!(env::block_timestamp() >= unfreezes_at, "The unfreeze delay has not passed yet");
+        let initial_storage_usage = env::storage_usage();
+        self.approval_freezes.remove(&owner_id);
+        refund_released_storage(owner_id, initial_storage_usage - env::storage_usage());
+    }
+
+    pub fn approval_freeze_of(&self, owner_id: AccountId) -> Option<ApprovalFreeze> {
+        self.approval_freezes
+            .get(&owner_id)
+            .map(|unfreezes_at| ApprovalFreeze { unfreezes_at: unfreezes_at.map(U64) })
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Approves accounts(3) as an operator of accounts(2) and freezes the approvals.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.approve_operator(accounts(3), None);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.freeze_approvals();
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The owner's approvals are frozen")]
+    fn test_frozen_operator_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_operator_transfer(accounts(2), accounts(0), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The unfreeze delay has not passed yet")]
+    fn test_unfreeze_before_delay() {
+        let (_, mut contract) = setup();
+        contract.request_unfreeze_approvals();
+        contract.unfreeze_approvals();
+    }
+
+    #[test]
+    fn test_unfreeze_after_delay() {
+        let (mut context, mut contract) = setup();
+        contract.request_unfreeze_approvals();
+        testing_env!(context.block_timestamp(UNFREEZE_DELAY).build());
+        contract.unfreeze_approvals();
+        assert!(contract.approval_freeze_of(accounts(2)).is_none());
+    }
+}
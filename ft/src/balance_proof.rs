@@ -0,0 +1,78 @@
+//! Balance proofs for token-gated contracts.
+//!
+//! A contract that needs to know whether an account holds enough tokens calls
+//! `verify_min_balance` with the name of one of its own methods. The token contract answers by
+//! calling that method back with the result, so the check happens inside the caller's own
+//! transaction instead of relying on a front-end read. The snapshot ID is the block height at
+//! which the balance was read.
+//!
+//! Anyone can call a contract's callback method directly with a forged proof, so the callback must
+//! check that `env::predecessor_account_id()` is this token contract before trusting the proof.
+
+use near_sdk::json_types::U128;
+use near_sdk::serde_json;
+use near_sdk::{env, near_bindgen, AccountId, Gas, Promise};
+
+use crate::types::BalanceProof;
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_BALANCE_PROOF_CALLBACK: Gas = Gas(10_000_000_000_000);
+
+#[near_bindgen]
+impl Contract {
+    /// Checks whether `account_id` holds at least `min_balance` and calls `callback` on the
+    /// caller with the resulting `BalanceProof` as the `proof` argument. The callback must check
+    /// that its predecessor is this contract. Takes `&mut self` since a view call can't schedule
+    /// the callback.
+    pub fn verify_min_balance(
+        &mut self,
+        account_id: AccountId,
+        min_balance: U128,
+        callback: String,
+    ) -> Promise {
+        let holds = self.token.accounts.get(&account_id).unwrap_or(0) >= min_balance.0;
+        let proof = BalanceProof {
+            account_id,
+            min_balance,
+            holds,
+            snapshot_id: env::block_height().into(),
+        };
+        Promise::new(env::predecessor_account_id()).function_call(
+            callback,
+            serde_json::json!({ "proof": proof }).to_string().into_bytes(),
+            0,
+            GAS_FOR_BALANCE_PROOF_CALLBACK,
+        )
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{get_context, TOTAL_SUPPLY};
+
+    #[test]
+    fn test_verify_min_balance() {
+        let mut context = get_context(accounts(1));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
+        testing_env!(context.predecessor_account_id(accounts(3)).block_index(42).build());
+        contract.verify_min_balance(accounts(1), TOTAL_SUPPLY.into(), "on_proof".to_string());
+
+        let receipt = get_created_receipts().pop().unwrap();
+        assert_eq!(receipt.receiver_id, accounts(3));
+        match &receipt.actions[0] {
+            VmAction::FunctionCall { function_name, args, .. } => {
+                assert_eq!(function_name, "on_proof");
+                let args: serde_json::Value = serde_json::from_slice(args).unwrap();
+                assert_eq!(args["proof"]["holds"], true);
+                assert_eq!(args["proof"]["snapshot_id"], "42");
+            }
+            _ => panic!("Expected a function call"),
+        }
+    }
+}
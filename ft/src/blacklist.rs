@@ -0,0 +1,111 @@
+//! Blacklist of accounts that can neither send nor receive tokens.
+//!
+//! The blacklist is managed by accounts with the compliance role. A blacklisted account keeps its
+//! balance, but every path that moves tokens rejects it as a sender or receiver, and refunds of
+//! its transfer calls are parked as claims it can only collect once it is removed from the list.
+
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::roles::{require_role, Role};
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Blacklists `account_id`. The attached deposit pays for storing the entry.
+    #[payable]
+    pub fn add_to_blacklist(&mut self, account_id: AccountId) {
+        require_role!(self, Role::Compliance);
+        let initial_storage_usage = env::storage_usage();
+        require!(self.blacklist.insert(&account_id), "The account is already blacklisted");
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    #[payable]
+    pub fn remove_from_blacklist(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        require_role!(self, Role::Compliance);
+        let initial_storage_usage = env::storage_usage();
+        require!(self.blacklist.remove(&account_id), "The account is not blacklisted");
+        refund_released_storage(
+            env::predecessor_account_id(),
+            initial_storage_usage - env::storage_usage(),
+        );
+    }
+
+    pub fn is_blacklisted(&self, account_id: AccountId) -> bool {
+        self.blacklist.contains(&account_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_assert_not_blacklisted(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+    ) {
+        require!(!self.blacklist.contains(sender_id), "The sender is blacklisted");
+        require!(!self.blacklist.contains(receiver_id), "The receiver is blacklisted");
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Registers accounts(1), sends it some tokens and blacklists it.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.add_to_blacklist(accounts(1));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The receiver is blacklisted")]
+    fn test_transfer_to_blacklisted() {
+        let (_, mut contract) = setup();
+        assert!(contract.is_blacklisted(accounts(1)));
+        contract.ft_transfer(accounts(1), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The sender is blacklisted")]
+    fn test_transfer_call_from_blacklisted() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer_call(accounts(2), 50.into(), None, String::new());
+    }
+
+    #[test]
+    fn test_refund_to_blacklisted_sender_is_parked() {
+        let (mut context, mut contract) = setup();
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed]
+        );
+        contract.ft_resolve_transfer(accounts(1), accounts(2), 100.into());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 200);
+        assert_eq!(contract.refund_claim_of(accounts(1)).unwrap().amount.0, 100);
+    }
+
+    #[test]
+    fn test_transfer_after_removal() {
+        let (_, mut contract) = setup();
+        contract.remove_from_blacklist(accounts(1));
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 200);
+    }
+}
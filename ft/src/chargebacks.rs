@@ -0,0 +1,239 @@
+//! Chargeback arbitration for merchant payments.
+//!
+//! Merchants opt in by setting a dispute window and funding a bond in tokens. Within the window
+//! after a payment, its payer can dispute it. An account with the arbiter role then either rejects
+//! the dispute or reverses the payment, which pays the unrefunded part of the payment back to the
+//! payer out of the merchant's bond, as far as the bond covers it. The bond can only be withdrawn,
+//! and the window changed, once the windows of all the payments made so far have passed and no
+//! dispute is open.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require};
+
+use crate::events::{FtDisputeResolved, FtPaymentDisputed};
+use crate::roles::{require_role, Role};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Dispute {
+    None,
+    Open,
+    Rejected,
+    Reversed,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Joins the chargeback program with the given dispute window (in nanoseconds), or leaves it
+    /// with a zero window. Leaving doesn't affect the disputes already open. The window can't be
+    /// changed while a payment made under it can still be disputed.
+    #[payable]
+    pub fn set_dispute_window(&mut self, dispute_window: U64) {
+        assert_one_yocto();
+        let merchant_id = env::predecessor_account_id();
+        let mut merchant = self.internal_unwrap_merchant(&merchant_id);
+        require!(
+            env::block_timestamp() >= merchant.disputable_until.0,
+            "Payments can still be disputed"
+        );
+        merchant.dispute_window = dispute_window;
+        self.merchants.insert(&merchant_id, &merchant);
+    }
+
+    /// Adds `amount` of the caller's tokens to its chargeback bond.
+    #[payable]
+    pub fn fund_chargeback_bond(&mut self, amount: U128) -> U128 {
+        assert_one_yocto();
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let merchant_id = env::predecessor_account_id();
+        let mut merchant = self.internal_unwrap_merchant(&merchant_id);
+        self.internal_hold(&merchant_id, amount.0, "chargeback bond");
+        merchant.bond = U128(merchant.bond.0 + amount.0);
+        self.merchants.insert(&merchant_id, &merchant);
+        merchant.bond
+    }
+
+    /// Returns `amount` of the caller's chargeback bond, which requires no open disputes and no
+    /// payment that can still be disputed.
+    #[payable]
+    pub fn withdraw_chargeback_bond(&mut self, amount: U128) -> U128 {
+        assert_one_yocto();
+        let merchant_id = env::predecessor_account_id();
+        let mut merchant = self.internal_unwrap_merchant(&merchant_id);
+        require!(merchant.open_disputes == 0, "The merchant has open disputes");
+        require!(
+            env::block_timestamp() >= merchant.disputable_until.0,
+            "Payments can still be disputed"
+        );
+        require!(amount.0 <= merchant.bond.0, "The amount exceeds the bond");
+        merchant.bond = U128(merchant.bond.0 - amount.0);
+        self.merchants.insert(&merchant_id, &merchant);
+        self.internal_release(&merchant_id, amount.0, "chargeback bond");
+        merchant.bond
+    }
+
+    /// Disputes the caller's payment `payment_id`, which must still be within its merchant's
+    /// dispute window.
+    #[payable]
+    pub fn dispute_payment(&mut self, payment_id: U64) {
+        assert_one_yocto();
+        let mut payment = self.internal_unwrap_merchant_payment(payment_id.0);
+        require!(
+            env::predecessor_account_id() == payment.payer_id,
+            "Only the payer can dispute the payment"
+        );
+        require!(payment.dispute == Dispute::None, "The payment was already disputed");
+        require!(payment.refunded.0 < payment.amount.0, "The payment was fully refunded");
+        let mut merchant = self.internal_unwrap_merchant(&payment.merchant_id);
+        require!(merchant.dispute_window.0 > 0, "The merchant isn't in the chargeback program");
+        require!(
+            env::block_timestamp() <= payment.paid_at.0 + merchant.dispute_window.0,
+            "The dispute window has passed"
+        );
+
+        payment.dispute = Dispute::Open;
+        self.merchant_payments.insert(&payment_id.0, &payment);
+        merchant.open_disputes += 1;
+        self.merchants.insert(&payment.merchant_id, &merchant);
+        FtPaymentDisputed {
+            payment_id,
+            merchant_id: &payment.merchant_id,
+            payer_id: &payment.payer_id,
+        }
+        .emit();
+    }
+
+    /// Settles the open dispute of `payment_id`. Reversing pays the unrefunded part of the
+    /// payment back to the payer out of the merchant's bond and returns the reversed amount.
+    #[payable]
+    pub fn resolve_dispute(&mut self, payment_id: U64, reverse: bool) -> U128 {
+        assert_one_yocto();
+        require_role!(self, Role::Arbiter);
+        let mut payment = self.internal_unwrap_merchant_payment(payment_id.0);
+        require!(payment.dispute == Dispute::Open, "The payment has no open dispute");
+        let mut merchant = self.internal_unwrap_merchant(&payment.merchant_id);
+        merchant.open_disputes -= 1;
+
+        let mut amount = 0;
+        if reverse {
+            amount = (payment.amount.0 - payment.refunded.0).min(merchant.bond.0);
+            merchant.bond = U128(merchant.bond.0 - amount);
+            payment.refunded = U128(payment.refunded.0 + amount);
+            payment.dispute = Dispute::Reversed;
+        } else {
+            payment.dispute = Dispute::Rejected;
+        }
+        self.merchants.insert(&payment.merchant_id, &merchant);
+        self.merchant_payments.insert(&payment_id.0, &payment);
+        if amount > 0 {
+            if self.token.accounts.contains_key(&payment.payer_id) {
+                self.internal_release(&payment.payer_id, amount, "chargeback");
+            } else {
+                self.internal_park_refund(payment.payer_id.clone(), amount);
+            }
+        }
+        FtDisputeResolved {
+            payment_id,
+            merchant_id: &payment.merchant_id,
+            payer_id: &payment.payer_id,
+            reversed: reverse,
+            amount: &U128(amount),
+        }
+        .emit();
+        amount.into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const DISPUTE_WINDOW: u64 = 1_000;
+
+    /// Registers accounts(1) as a merchant in the program with a bond of 500, has accounts(2) pay
+    /// it 100 and dispute the payment.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        contract.ft_transfer(accounts(1), 500.into(), None);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 2000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.register_merchant(accounts(1), 0.into());
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.set_dispute_window(DISPUTE_WINDOW.into());
+        contract.fund_chargeback_bond(500.into());
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_pay_merchant(accounts(1), 100.into(), None);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.dispute_payment(0.into());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_reverse_payment_from_bond() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.resolve_dispute(0.into(), true).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 500);
+        let merchant = contract.merchant(accounts(1)).unwrap();
+        assert_eq!(merchant.bond.0, 400);
+        assert_eq!(merchant.pending.0, 100);
+        assert_eq!(contract.merchant_payment(0.into()).unwrap().dispute, Dispute::Reversed);
+    }
+
+    #[test]
+    #[should_panic(expected = "The caller doesn't have the arbiter role")]
+    fn test_resolve_dispute_by_non_arbiter() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.resolve_dispute(0.into(), true);
+    }
+
+    #[test]
+    #[should_panic(expected = "The merchant has open disputes")]
+    fn test_withdraw_bond_with_open_dispute() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.withdraw_chargeback_bond(500.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Payments can still be disputed")]
+    fn test_withdraw_bond_within_dispute_window() {
+        let (mut context, mut contract) = setup();
+        contract.resolve_dispute(0.into(), false);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.withdraw_chargeback_bond(500.into());
+    }
+
+    #[test]
+    #[should_panic(expected = "Payments can still be disputed")]
+    fn test_shorten_dispute_window_within_window() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.set_dispute_window(1.into());
+    }
+
+    #[test]
+    fn test_withdraw_bond_after_dispute_window() {
+        let (mut context, mut contract) = setup();
+        contract.resolve_dispute(0.into(), false);
+        testing_env!(context
+            .block_timestamp(DISPUTE_WINDOW + 1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert_eq!(contract.withdraw_chargeback_bond(500.into()).0, 0);
+    }
+}
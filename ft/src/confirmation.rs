@@ -0,0 +1,282 @@
+//! Opt-in recipient confirmation (two-phase transfers).
+//!
+//! Accounts that don't want to receive unsolicited deposits can require every incoming
+//! `ft_transfer` to be accepted first. Until then the tokens are held by the contract; the
+//! receiver can accept or reject the transfer, and the sender can reclaim it once the receiver's
+//! acceptance period has passed. The sender's attached deposit pays for storing a pending
+//! transfer, and the storage is refunded to the sender once the transfer is settled. The number
+//! of transfers waiting for a single receiver is capped.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, StorageUsage};
+
+use crate::events::FtTransferPending;
+use crate::utils::{assert_stored_memo, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of transfers that can wait for the same receiver at once.
+const MAX_PENDING_TRANSFERS: u32 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct TransferConfirmation {
+    /// How long incoming transfers wait for acceptance, in nanoseconds.
+    pub acceptance_period: u64,
+    /// Number of transfers currently waiting for this receiver.
+    pub pending: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingTransfer {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    /// Block timestamp (in nanoseconds) after which the sender can reclaim the transfer.
+    pub expires_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Requires incoming transfers to the caller to be accepted within `acceptance_period`
+    /// nanoseconds, or stops requiring it when `None` is passed. The attached deposit pays for
+    /// storing the setting.
+    #[payable]
+    pub fn set_transfer_confirmation(&mut self, acceptance_period: Option<U64>) {
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        let pending = self.transfer_confirmations.get(&account_id).map_or(0, |c| c.pending);
+        match acceptance_period {
+            Some(period) => {
+                self.transfer_confirmations.insert(
+                    &account_id,
+                    &TransferConfirmation { acceptance_period: period.0, pending },
+                );
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                assert_one_yocto();
+                require!(pending == 0, "Settle the pending transfers first");
+                self.transfer_confirmations.remove(&account_id);
+            }
+        }
+    }
+
+    /// Credits the pending transfer `transfer_id` to the caller, who must be its receiver.
+    #[payable]
+    pub fn accept_transfer(&mut self, transfer_id: u64) {
+        assert_one_yocto();
+        let transfer = self.internal_unwrap_pending_transfer(transfer_id);
+        require!(
+            env::predecessor_account_id() == transfer.receiver_id,
+            "Only the receiver can accept the transfer"
+        );
+        self.internal_settle_pending_transfer(transfer_id, transfer, true);
+    }
+
+    /// Returns the pending transfer `transfer_id` to its sender. Can only be called by the
+    /// receiver, at any time before the transfer is settled.
+    #[payable]
+    pub fn reject_transfer(&mut self, transfer_id: u64) {
+        assert_one_yocto();
+        let transfer = self.internal_unwrap_pending_transfer(transfer_id);
+        require!(
+            env::predecessor_account_id() == transfer.receiver_id,
+            "Only the receiver can reject the transfer"
+        );
+        self.internal_settle_pending_transfer(transfer_id, transfer, false);
+    }
+
+    /// Returns the expired pending transfer `transfer_id` to the caller, who must be its sender.
+    #[payable]
+    pub fn reclaim_transfer(&mut self, transfer_id: u64) {
+        assert_one_yocto();
+        let transfer = self.internal_unwrap_pending_transfer(transfer_id);
+        require!(
+            env::predecessor_account_id() == transfer.sender_id,
+            "Only the sender can reclaim the transfer"
+        );
+        require!(
+            env::block_timestamp() >= transfer.expires_at.0,
+            "The transfer can't be reclaimed before it expires"
+        );
+        self.internal_settle_pending_transfer(transfer_id, transfer, false);
+    }
+
+    pub fn pending_transfer(&self, transfer_id: u64) -> Option<PendingTransfer> {
+        self.pending_transfers.get(&transfer_id)
+    }
+
+    /// Returns the acceptance period of `account_id` if it requires confirmed transfers.
+    pub fn transfer_confirmation_of(&self, account_id: AccountId) -> Option<U64> {
+        self.transfer_confirmations.get(&account_id).map(|c| U64(c.acceptance_period))
+    }
+}
+
+impl Contract {
+    /// Holds `amount` from the sender until the receiver settles the transfer. Returns the
+    /// storage taken by the pending transfer, which the caller charges to the sender.
+    pub(crate) fn internal_create_pending_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> StorageUsage {
+        require!(sender_id != receiver_id, "Sender and receiver should be different");
+        require!(amount > 0, "The amount should be a positive number");
+        let mut confirmation = self
+            .transfer_confirmations
+            .get(receiver_id)
+            .unwrap_or_else(|| env::panic_str("The receiver doesn't require confirmed transfers"));
+        require!(
+            confirmation.pending < MAX_PENDING_TRANSFERS,
+            "Too many transfers are waiting for the receiver"
+        );
+        assert_stored_memo(&memo);
+        confirmation.pending += 1;
+        self.transfer_confirmations.insert(receiver_id, &confirmation);
+        self.internal_hold(sender_id, amount, "pending transfer");
+
+        let transfer_id = self.next_pending_transfer_id;
+        self.next_pending_transfer_id += 1;
+        let expires_at = U64(env::block_timestamp() + confirmation.acceptance_period);
+        let initial_storage_usage = env::storage_usage();
+        self.pending_transfers.insert(
+            &transfer_id,
+            &PendingTransfer {
+                sender_id: sender_id.clone(),
+                receiver_id: receiver_id.clone(),
+                amount: U128(amount),
+                memo,
+                expires_at,
+            },
+        );
+        FtTransferPending {
+            transfer_id,
+            sender_id,
+            receiver_id,
+            amount: &U128(amount),
+            expires_at,
+        }
+        .emit();
+        env::storage_usage() - initial_storage_usage
+    }
+
+    fn internal_settle_pending_transfer(
+        &mut self,
+        transfer_id: u64,
+        transfer: PendingTransfer,
+        accepted: bool,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.pending_transfers.remove(&transfer_id);
+        refund_released_storage(
+            transfer.sender_id.clone(),
+            initial_storage_usage - env::storage_usage(),
+        );
+        if let Some(mut confirmation) = self.transfer_confirmations.get(&transfer.receiver_id) {
+            confirmation.pending -= 1;
+            self.transfer_confirmations.insert(&transfer.receiver_id, &confirmation);
+        }
+        if accepted {
+            self.internal_release(
+                &transfer.receiver_id,
+                transfer.amount.into(),
+                transfer.memo.as_deref().unwrap_or("accepted transfer"),
+            );
+        } else {
+            self.internal_release(&transfer.sender_id, transfer.amount.into(), "returned transfer");
+        }
+    }
+
+    fn internal_unwrap_pending_transfer(&self, transfer_id: u64) -> PendingTransfer {
+        self.pending_transfers
+            .get(&transfer_id)
+            .unwrap_or_else(|| env::panic_str("The pending transfer doesn't exist"))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{last_refund_to, setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const ACCEPTANCE_PERIOD: u64 = 1_000;
+
+    /// Registers accounts(1) with confirmation required and leaves accounts(2) as the caller.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_transfer_confirmation(Some(ACCEPTANCE_PERIOD.into()));
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_accept_transfer() {
+        let (mut context, mut contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.accept_transfer(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert!(contract.pending_transfer(0).is_none());
+    }
+
+    #[test]
+    fn test_reclaim_expired_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(ACCEPTANCE_PERIOD).build());
+        contract.reclaim_transfer(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+    }
+
+    #[test]
+    #[should_panic(expected = "The transfer can't be reclaimed before it expires")]
+    fn test_reclaim_before_expiry() {
+        let (_, mut contract) = setup();
+        contract.reclaim_transfer(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_pending_transfer_without_storage_deposit() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 100.into(), None);
+    }
+
+    #[test]
+    fn test_pending_transfer_storage_refunded_on_accept() {
+        let (mut context, mut contract) = setup();
+        let deposit = env::storage_byte_cost() * 1000;
+        testing_env!(context.attached_deposit(deposit).build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        let unused = last_refund_to(accounts(2));
+        assert!(unused < deposit);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.accept_transfer(1);
+        assert_eq!(last_refund_to(accounts(2)), deposit - unused);
+    }
+}
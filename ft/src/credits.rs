@@ -0,0 +1,195 @@
+//! Pending credits for receivers that are not registered yet.
+//!
+//! `ft_transfer_or_park` sends tokens like `ft_transfer` when the receiver is registered.
+//! Otherwise the tokens are held by the contract as a credit the receiver can claim with
+//! `claim_credit` once it registers. If the credit is still unclaimed after
+//! `CREDIT_CLAIM_PERIOD`, the sender can cancel it and get the tokens back. The sender pays for
+//! storing the credit and gets the storage refunded when the credit is settled.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::FtTransferParked;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// How long the receiver has to claim a credit before the sender can cancel it, in nanoseconds.
+const CREDIT_CLAIM_PERIOD: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct PendingCredit {
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    /// Block timestamp (in nanoseconds) after which the sender can cancel the credit.
+    pub expires_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers `amount` to `receiver_id`, or parks it as a credit if the receiver is not
+    /// registered. Requires at least 1 yoctoNEAR attached, plus the storage cost of the credit
+    /// when one is created.
+    #[payable]
+    pub fn ft_transfer_or_park(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        let sender_id = env::predecessor_account_id();
+        if self.token.accounts.contains_key(&receiver_id) {
+            refund_deposit(self.internal_ft_transfer(sender_id, receiver_id, amount.into(), memo));
+            return;
+        }
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(
+            !self.internal_exceeds_soft_limit(&sender_id, amount.into()),
+            "The amount exceeds the soft limit, use ft_transfer to queue it for approval"
+        );
+        let initial_storage_usage = env::storage_usage();
+        let amount = U128(self.internal_charge_fee(&sender_id, &receiver_id, amount.0));
+        self.internal_hold(&sender_id, amount.into(), "pending credit");
+
+        let credit_id = self.next_pending_credit_id;
+        self.next_pending_credit_id += 1;
+        let expires_at = U64(env::block_timestamp() + CREDIT_CLAIM_PERIOD);
+        self.pending_credits.insert(
+            &credit_id,
+            &PendingCredit {
+                sender_id: sender_id.clone(),
+                receiver_id: receiver_id.clone(),
+                amount,
+                memo,
+                expires_at,
+            },
+        );
+        FtTransferParked {
+            credit_id,
+            sender_id: &sender_id,
+            receiver_id: &receiver_id,
+            amount: &amount,
+            expires_at,
+        }
+        .emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Credits the pending credit `credit_id` to the caller, who must be its receiver and
+    /// registered by now.
+    #[payable]
+    pub fn claim_credit(&mut self, credit_id: u64) {
+        assert_one_yocto();
+        let credit = self.internal_unwrap_pending_credit(credit_id);
+        require!(
+            env::predecessor_account_id() == credit.receiver_id,
+            "Only the receiver can claim the credit"
+        );
+        self.token.internal_unwrap_balance_of(&credit.receiver_id);
+        let memo = credit.memo.clone();
+        self.internal_settle_pending_credit(credit_id, credit, true, memo.as_deref());
+    }
+
+    /// Returns the expired credit `credit_id` to the caller, who must be its sender.
+    #[payable]
+    pub fn cancel_credit(&mut self, credit_id: u64) {
+        assert_one_yocto();
+        let credit = self.internal_unwrap_pending_credit(credit_id);
+        require!(
+            env::predecessor_account_id() == credit.sender_id,
+            "Only the sender can cancel the credit"
+        );
+        require!(
+            env::block_timestamp() >= credit.expires_at.0,
+            "The credit can't be cancelled before it expires"
+        );
+        self.internal_settle_pending_credit(credit_id, credit, false, None);
+    }
+
+    pub fn pending_credit(&self, credit_id: u64) -> Option<PendingCredit> {
+        self.pending_credits.get(&credit_id)
+    }
+}
+
+impl Contract {
+    fn internal_settle_pending_credit(
+        &mut self,
+        credit_id: u64,
+        credit: PendingCredit,
+        claimed: bool,
+        memo: Option<&str>,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.pending_credits.remove(&credit_id);
+        if claimed {
+            self.internal_release(
+                &credit.receiver_id,
+                credit.amount.into(),
+                memo.unwrap_or("claimed credit"),
+            );
+        } else {
+            self.internal_release(&credit.sender_id, credit.amount.into(), "cancelled credit");
+        }
+        refund_released_storage(
+            credit.sender_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+    }
+
+    fn internal_unwrap_pending_credit(&self, credit_id: u64) -> PendingCredit {
+        self.pending_credits
+            .get(&credit_id)
+            .unwrap_or_else(|| env::panic_str("The pending credit doesn't exist"))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{register, setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Parks a credit of 100 from accounts(2) to the unregistered accounts(1).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.ft_transfer_or_park(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 100);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_claim_credit() {
+        let (mut context, mut contract) = setup();
+        register(&mut context, &mut contract, accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.claim_credit(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert!(contract.pending_credit(0).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The credit can't be cancelled before it expires")]
+    fn test_cancel_credit_before_expiry() {
+        let (_, mut contract) = setup();
+        contract.cancel_credit(0);
+    }
+
+    #[test]
+    fn test_cancel_expired_credit() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(CREDIT_CLAIM_PERIOD).build());
+        contract.cancel_credit(0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+    }
+}
@@ -0,0 +1,171 @@
+//! Custodial accounts administered by a guardian.
+//!
+//! An account (the ward) can put itself under the custody of a guardian until a release time,
+//! e.g. a child's birthday. Until then, the ward can only move up to its spend limit per transfer
+//! on its own; larger spends are made by the guardian with `ward_transfer`. The guardian can change
+//! the spend limit and end the custody early. Once the release time has passed the custody no
+//! longer restricts anything, and anyone can remove the record to refund its storage to the ward.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Custody {
+    pub guardian_id: AccountId,
+    /// Block timestamp (in nanoseconds) from which the ward is on its own.
+    pub release_at: U64,
+    /// Maximum amount the ward can move per transfer without the guardian.
+    pub spend_limit: U128,
+}
+
+impl Custody {
+    fn is_active(&self) -> bool {
+        env::block_timestamp() < self.release_at.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Puts the caller under the custody of `guardian_id` until `release_at`. The attached
+    /// deposit pays for storing the custody.
+    #[payable]
+    pub fn enter_custody(&mut self, guardian_id: AccountId, release_at: U64, spend_limit: U128) {
+        let ward_id = env::predecessor_account_id();
+        require!(ward_id != guardian_id, "An account can't be its own guardian");
+        require!(release_at.0 > env::block_timestamp(), "The release time must be in the future");
+        self.token.internal_unwrap_balance_of(&ward_id);
+        require!(
+            !self.custodies.get(&ward_id).map_or(false, |custody| custody.is_active()),
+            "The account is already under custody"
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.custodies.insert(&ward_id, &Custody { guardian_id, release_at, spend_limit });
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Sets the amount `ward_id` can move per transfer on its own. Only callable by its guardian.
+    #[payable]
+    pub fn set_ward_spend_limit(&mut self, ward_id: AccountId, spend_limit: U128) {
+        assert_one_yocto();
+        let mut custody = self.internal_unwrap_guarded_custody(&ward_id);
+        custody.spend_limit = spend_limit;
+        self.custodies.insert(&ward_id, &custody);
+    }
+
+    /// Transfers `amount` of `ward_id`'s tokens to `receiver_id` regardless of the spend limit.
+    /// Only callable by the ward's guardian.
+    #[payable]
+    pub fn ward_transfer(
+        &mut self,
+        ward_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        self.internal_unwrap_guarded_custody(&ward_id);
+        refund_deposit(self.internal_ft_transfer(ward_id, receiver_id, amount.into(), memo));
+    }
+
+    /// Ends the custody of `ward_id`, which the guardian can do at any time and anyone else once
+    /// the release time has passed. The storage is refunded to the ward.
+    #[payable]
+    pub fn release_custody(&mut self, ward_id: AccountId) {
+        assert_one_yocto();
+        let custody = self
+            .custodies
+            .get(&ward_id)
+            .unwrap_or_else(|| env::panic_str("The account isn't under custody"));
+        require!(
+            !custody.is_active() || env::predecessor_account_id() == custody.guardian_id,
+            "Only the guardian can release the custody before the release time"
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.custodies.remove(&ward_id);
+        refund_released_storage(ward_id, initial_storage_usage - env::storage_usage());
+    }
+
+    /// Returns the custody restricting `ward_id`, if it is still running.
+    pub fn custody_of(&self, ward_id: AccountId) -> Option<Custody> {
+        self.custodies.get(&ward_id).filter(Custody::is_active)
+    }
+}
+
+impl Contract {
+    /// Returns the running custody of `ward_id`, panicking unless the caller is its guardian.
+    fn internal_unwrap_guarded_custody(&self, ward_id: &AccountId) -> Custody {
+        let custody = self
+            .custodies
+            .get(ward_id)
+            .filter(Custody::is_active)
+            .unwrap_or_else(|| env::panic_str("The account isn't under custody"));
+        require!(
+            env::predecessor_account_id() == custody.guardian_id,
+            "Only the guardian can manage the ward"
+        );
+        custody
+    }
+
+    /// Panics if `account_id` is a ward moving more than its spend limit without its guardian.
+    pub(crate) fn internal_assert_custody_allows(&self, account_id: &AccountId, amount: Balance) {
+        if let Some(custody) = self.custodies.get(account_id).filter(Custody::is_active) {
+            require!(
+                amount <= custody.spend_limit.0
+                    || env::predecessor_account_id() == custody.guardian_id,
+                "The amount exceeds the ward's spend limit"
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    const RELEASE_AT: u64 = 1_000;
+
+    /// Puts accounts(2) under the custody of accounts(3) with a spend limit of 100.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.enter_custody(accounts(3), RELEASE_AT.into(), 100.into());
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the ward's spend limit")]
+    fn test_ward_transfer_above_limit() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(0), 101.into(), None);
+    }
+
+    #[test]
+    fn test_guardian_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ward_transfer(accounts(2), accounts(0), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 1000);
+    }
+
+    #[test]
+    fn test_transfer_after_release() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(RELEASE_AT).build());
+        assert!(contract.custody_of(accounts(2)).is_none());
+        contract.ft_transfer(accounts(0), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 1000);
+    }
+}
@@ -0,0 +1,109 @@
+//! Human-readable display handles.
+//!
+//! Accounts can register a short handle that is logged next to their account ID whenever they
+//! send or receive a transfer, so activity feeds can show names without an identity service.
+//! Handles are only labels: they are not unique and don't resolve to accounts.
+
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::FtTransferHandles;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+const MAX_HANDLE_LEN: usize = 32;
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the caller's display handle, or removes it when `None` is passed. Handles are made of
+    /// ASCII letters, digits, `_` and `-`. The attached deposit pays for storing the handle.
+    #[payable]
+    pub fn set_display_handle(&mut self, handle: Option<String>) {
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        match handle {
+            Some(handle) => {
+                require!(
+                    !handle.is_empty()
+                        && handle.len() <= MAX_HANDLE_LEN
+                        && handle
+                            .bytes()
+                            .all(|c| c.is_ascii_alphanumeric() || c == b'_' || c == b'-'),
+                    "Invalid display handle"
+                );
+                self.display_handles.insert(&account_id, &handle);
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                assert_one_yocto();
+                self.display_handles.remove(&account_id);
+                refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+            }
+        }
+    }
+
+    pub fn display_handle_of(&self, account_id: AccountId) -> Option<String> {
+        self.display_handles.get(&account_id)
+    }
+}
+
+impl Contract {
+    /// Logs the handles of the accounts of a transfer, if either of them has one.
+    pub(crate) fn internal_emit_transfer_handles(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        let sender_handle = self.display_handles.get(sender_id);
+        let receiver_handle = self.display_handles.get(receiver_id);
+        if sender_handle.is_some() || receiver_handle.is_some() {
+            FtTransferHandles {
+                old_owner_id: sender_id,
+                old_owner_handle: sender_handle.as_deref(),
+                new_owner_id: receiver_id,
+                new_owner_handle: receiver_handle.as_deref(),
+                amount: &U128(amount),
+            }
+            .emit();
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Registers accounts(1), the owner being accounts(2).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_logs_handles() {
+        let (mut context, mut contract) = setup();
+        contract.set_display_handle(Some("treasury".to_string()));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+
+        let log = get_logs().pop().unwrap();
+        assert!(log.contains(r#""event":"ft_transfer_handles""#));
+        assert!(log.contains(r#""old_owner_handle":"treasury""#));
+        assert!(!log.contains("new_owner_handle"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid display handle")]
+    fn test_invalid_handle() {
+        let (_, mut contract) = setup();
+        contract.set_display_handle(Some("no spaces".to_string()));
+    }
+}
@@ -0,0 +1,244 @@
+//! Merkle-root claim distributor for large airdrops.
+//!
+//! The owner commits to a list of `(index, account, amount)` allocations by publishing the root
+//! of their Merkle tree, and locks the total allocation from its balance. Each account then claims
+//! its own allocation with a proof. Leaves are the sha256 of the Borsh encoding of
+//! `(index, account_id, amount)`, and inner nodes the sha256 of their two children in ascending
+//! order, so proofs don't need to say on which side each sibling is. Claimed leaves are marked in
+//! a bitmap keyed by their index; the claimer pays for the bitmap words it creates.
+//!
+//! Every distribution has a claim window. Once it has expired, the owner can sweep the unclaimed
+//! tokens to an account of its choice with `sweep_unclaimed`.
+
+use std::convert::TryInto;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::{FtDistributionClaimed, FtDistributionCreated, FtDistributionSwept};
+use crate::types::DistributionProgress;
+use crate::utils::refund_deposit;
+use crate::{Contract, ContractExt};
+
+pub type MerkleHash = [u8; 32];
+
+/// Maximum depth of a proof, enough for 2^32 leaves.
+const MAX_PROOF_LEN: usize = 32;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Distribution {
+    pub root: MerkleHash,
+    /// Tokens locked for the distribution.
+    pub total: U128,
+    pub claimed: U128,
+    /// Unclaimed tokens recovered by the owner after the expiry.
+    pub swept: U128,
+    /// Block timestamp (in nanoseconds) from which allocations can't be claimed anymore.
+    pub expires_at: U64,
+}
+
+impl Distribution {
+    fn remaining(&self) -> Balance {
+        self.total.0 - self.claimed.0 - self.swept.0
+    }
+
+    fn is_expired(&self) -> bool {
+        env::block_timestamp() >= self.expires_at.0
+    }
+}
+
+/// Returns the leaf of the allocation of `amount` to `account_id` at `index`.
+pub fn merkle_leaf(index: u64, account_id: &AccountId, amount: Balance) -> MerkleHash {
+    hash(&(index, account_id, amount).try_to_vec().unwrap())
+}
+
+fn hash(data: &[u8]) -> MerkleHash {
+    env::sha256(data).try_into().unwrap()
+}
+
+fn hash_pair(a: &MerkleHash, b: &MerkleHash) -> MerkleHash {
+    let (first, second) = if a <= b { (a, b) } else { (b, a) };
+    hash(&[first.as_slice(), second.as_slice()].concat())
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `total` of the owner's tokens for the allocations committed to by `root`, claimable
+    /// until `expires_at`, and returns the id of the distribution. The attached deposit pays for
+    /// storing it.
+    #[payable]
+    pub fn create_distribution(&mut self, root: MerkleHash, total: U128, expires_at: U64) -> u64 {
+        self.assert_owner();
+        require!(total.0 > 0, "The total should be a positive number");
+        require!(expires_at.0 > env::block_timestamp(), "The expiry must be in the future");
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.owner_id.clone();
+        self.internal_hold(&owner_id, total.0, "distribution");
+        let distribution_id = self.next_distribution_id;
+        self.next_distribution_id += 1;
+        self.distributions.insert(
+            &distribution_id,
+            &Distribution { root, total, claimed: U128(0), swept: U128(0), expires_at },
+        );
+        FtDistributionCreated { distribution_id, total: &total }.emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        distribution_id
+    }
+
+    /// Transfers the caller's allocation of `amount` at `index` in `distribution_id`, proven by
+    /// the sibling hashes `proof` from the leaf up to the root.
+    #[payable]
+    pub fn claim(
+        &mut self,
+        distribution_id: u64,
+        index: u64,
+        amount: U128,
+        proof: Vec<MerkleHash>,
+    ) {
+        require!(proof.len() <= MAX_PROOF_LEN, "The proof is too long");
+        let account_id = env::predecessor_account_id();
+        let mut distribution = self.internal_unwrap_distribution(distribution_id);
+        require!(!distribution.is_expired(), "The distribution has expired");
+        let root = proof.iter().fold(merkle_leaf(index, &account_id, amount.0), |node, sibling| {
+            hash_pair(&node, sibling)
+        });
+        require!(root == distribution.root, "Invalid proof");
+
+        let initial_storage_usage = env::storage_usage();
+        let key = (distribution_id, index / 64);
+        let word = self.claimed_leaves.get(&key).unwrap_or(0);
+        let bit = 1u64 << (index % 64);
+        require!(word & bit == 0, "The allocation was already claimed");
+        self.claimed_leaves.insert(&key, &(word | bit));
+        distribution.claimed = U128(distribution.claimed.0 + amount.0);
+        require!(distribution.claimed.0 <= distribution.total.0, "The distribution is exhausted");
+        self.distributions.insert(&distribution_id, &distribution);
+
+        self.internal_release(&account_id, amount.0, "distribution");
+        FtDistributionClaimed { distribution_id, account_id: &account_id, amount: &amount }.emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Sends the unclaimed tokens of the expired distribution `distribution_id` to
+    /// `destination` and returns the amount.
+    #[payable]
+    pub fn sweep_unclaimed(&mut self, distribution_id: u64, destination: AccountId) -> U128 {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut distribution = self.internal_unwrap_distribution(distribution_id);
+        require!(distribution.is_expired(), "The distribution hasn't expired yet");
+        let amount = distribution.remaining();
+        require!(amount > 0, "Nothing left to sweep");
+        distribution.swept = U128(distribution.swept.0 + amount);
+        self.distributions.insert(&distribution_id, &distribution);
+        self.internal_release(&destination, amount, "unclaimed distribution");
+        FtDistributionSwept { distribution_id, destination: &destination, amount: &U128(amount) }
+            .emit();
+        amount.into()
+    }
+
+    pub fn distribution(&self, distribution_id: u64) -> Option<Distribution> {
+        self.distributions.get(&distribution_id)
+    }
+
+    pub fn distribution_progress(&self, distribution_id: u64) -> DistributionProgress {
+        let distribution = self.internal_unwrap_distribution(distribution_id);
+        DistributionProgress {
+            claimed: distribution.claimed,
+            remaining: distribution.remaining().into(),
+            expired: distribution.is_expired(),
+        }
+    }
+
+    pub fn is_claimed(&self, distribution_id: u64, index: u64) -> bool {
+        let word = self.claimed_leaves.get(&(distribution_id, index / 64)).unwrap_or(0);
+        word & (1 << (index % 64)) != 0
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_distribution(&self, distribution_id: u64) -> Distribution {
+        self.distributions
+            .get(&distribution_id)
+            .unwrap_or_else(|| env::panic_str("The distribution doesn't exist"))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{register, setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const EXPIRES_AT: u64 = 1_000;
+
+    /// Distributes 100 to accounts(1) at index 0 and 200 to accounts(3) at index 1, registers
+    /// accounts(1) and returns its proof.
+    fn setup() -> (VMContextBuilder, Contract, Vec<MerkleHash>) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let leaves = [merkle_leaf(0, &accounts(1), 100), merkle_leaf(1, &accounts(3), 200)];
+        testing_env!(with_storage_deposit(&mut context).build());
+        let root = hash_pair(&leaves[0], &leaves[1]);
+        contract.create_distribution(root, 300.into(), EXPIRES_AT.into());
+        register(&mut context, &mut contract, accounts(1));
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        (context, contract, vec![leaves[1]])
+    }
+
+    #[test]
+    fn test_claim() {
+        let (_, mut contract, proof) = setup();
+        contract.claim(0, 0, 100.into(), proof);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert!(contract.is_claimed(0, 0));
+        assert!(!contract.is_claimed(0, 1));
+        assert_eq!(contract.distribution(0).unwrap().claimed.0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The allocation was already claimed")]
+    fn test_claim_twice() {
+        let (_, mut contract, proof) = setup();
+        contract.claim(0, 0, 100.into(), proof.clone());
+        contract.claim(0, 0, 100.into(), proof);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid proof")]
+    fn test_claim_with_altered_amount() {
+        let (_, mut contract, proof) = setup();
+        contract.claim(0, 0, 200.into(), proof);
+    }
+
+    #[test]
+    #[should_panic(expected = "The distribution has expired")]
+    fn test_claim_after_expiry() {
+        let (mut context, mut contract, proof) = setup();
+        testing_env!(context.block_timestamp(EXPIRES_AT).build());
+        contract.claim(0, 0, 100.into(), proof);
+    }
+
+    #[test]
+    fn test_sweep_unclaimed() {
+        let (mut context, mut contract, proof) = setup();
+        contract.claim(0, 0, 100.into(), proof);
+        testing_env!(context
+            .block_timestamp(EXPIRES_AT)
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        assert_eq!(contract.sweep_unclaimed(0, accounts(2)).0, 200);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+        let progress = contract.distribution_progress(0);
+        assert_eq!((progress.claimed.0, progress.remaining.0), (100, 0));
+    }
+}
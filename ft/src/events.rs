@@ -0,0 +1,659 @@
+//! Contract-specific events in the [NEP-297] format.
+//!
+//! The standard NEP-141 events (`ft_mint`, `ft_transfer`, `ft_burn`) are emitted through
+//! `near_contract_standards::fungible_token::events`. Everything this contract adds on top of the
+//! standard is logged here under its own `standard` name, so indexers can tell the two apart.
+//!
+//! [NEP-297]: https://github.com/near/NEPs/blob/master/specs/Standards/EventsFormat.md
+
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, AccountId};
+
+use crate::pause::Feature;
+use crate::roles::Role;
+
+const EVENT_STANDARD: &str = "ft-ext";
+const EVENT_STANDARD_VERSION: &str = "1.0.0";
+
+/// A sponsor registered a new account and sent it its first tokens.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtSponsoredTransfer<'a> {
+    pub sponsor_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub storage_deposit: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub campaign: Option<&'a str>,
+}
+
+impl FtSponsoredTransfer<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtSponsoredTransfer(&[self]))
+    }
+}
+
+/// A transfer above the owner's soft limit was queued for the approver.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTransferQueued<'a> {
+    pub transfer_id: u64,
+    pub owner_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub approver_id: &'a AccountId,
+}
+
+impl FtTransferQueued<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtTransferQueued(&[self]))
+    }
+}
+
+/// A transfer to an account that requires confirmation is waiting for it to be accepted.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTransferPending<'a> {
+    pub transfer_id: u64,
+    pub sender_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub expires_at: U64,
+}
+
+impl FtTransferPending<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtTransferPending(&[self]))
+    }
+}
+
+/// An account moved its balance and approvals to a new account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtAccountMigrated<'a> {
+    pub old_account_id: &'a AccountId,
+    pub new_account_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtAccountMigrated<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtAccountMigrated(&[self]))
+    }
+}
+
+/// Display handles of the accounts of a transfer, emitted right after its `ft_transfer` event.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTransferHandles<'a> {
+    pub old_owner_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub old_owner_handle: Option<&'a str>,
+    pub new_owner_id: &'a AccountId,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub new_owner_handle: Option<&'a str>,
+    pub amount: &'a U128,
+}
+
+impl FtTransferHandles<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtTransferHandles(&[self]))
+    }
+}
+
+/// A transfer to an unregistered account was parked until the receiver registers.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTransferParked<'a> {
+    pub credit_id: u64,
+    pub sender_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub expires_at: U64,
+}
+
+impl FtTransferParked<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtTransferParked(&[self]))
+    }
+}
+
+/// The owner proposed an account as the next owner.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtOwnershipProposed<'a> {
+    pub owner_id: &'a AccountId,
+    pub pending_owner_id: &'a AccountId,
+}
+
+impl FtOwnershipProposed<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtOwnershipProposed(&[self]))
+    }
+}
+
+/// The owner withdrew its proposal of the next owner.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtOwnershipProposalCancelled<'a> {
+    pub owner_id: &'a AccountId,
+    pub pending_owner_id: &'a AccountId,
+}
+
+impl FtOwnershipProposalCancelled<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtOwnershipProposalCancelled(&[self]))
+    }
+}
+
+/// The proposed owner accepted the ownership of the token.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtOwnerChanged<'a> {
+    pub old_owner_id: &'a AccountId,
+    pub new_owner_id: &'a AccountId,
+}
+
+impl FtOwnerChanged<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtOwnerChanged(&[self]))
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+#[serde(tag = "event", content = "data")]
+#[serde(rename_all = "snake_case")]
+#[allow(clippy::enum_variant_names)]
+enum EventKind<'a> {
+    FtSponsoredTransfer(&'a [FtSponsoredTransfer<'a>]),
+    FtTransferQueued(&'a [FtTransferQueued<'a>]),
+    FtTransferPending(&'a [FtTransferPending<'a>]),
+    FtAccountMigrated(&'a [FtAccountMigrated<'a>]),
+    FtTransferHandles(&'a [FtTransferHandles<'a>]),
+    FtTransferParked(&'a [FtTransferParked<'a>]),
+    FtOwnershipProposed(&'a [FtOwnershipProposed<'a>]),
+    FtOwnershipProposalCancelled(&'a [FtOwnershipProposalCancelled<'a>]),
+    FtOwnerChanged(&'a [FtOwnerChanged<'a>]),
+    FtRoleGranted(&'a [FtRoleGranted<'a>]),
+    FtRoleRevoked(&'a [FtRoleRevoked<'a>]),
+    FtPaused(&'a [FtPaused<'a>]),
+    FtUnpaused(&'a [FtUnpaused<'a>]),
+    FtSeize(&'a [FtSeize<'a>]),
+    FtMetadataUpdated(&'a [FtMetadataUpdated<'a>]),
+    FtReferenceUpdated(&'a [FtReferenceUpdated<'a>]),
+    FtApproval(&'a [FtApproval<'a>]),
+    FtOperatorApproval(&'a [FtOperatorApproval<'a>]),
+    FtVestingCreated(&'a [FtVestingCreated<'a>]),
+    FtVestingClaimed(&'a [FtVestingClaimed<'a>]),
+    FtMerchantPayment(&'a [FtMerchantPayment<'a>]),
+    FtMerchantSettled(&'a [FtMerchantSettled<'a>]),
+    FtVestingRevoked(&'a [FtVestingRevoked<'a>]),
+    FtMerchantRefund(&'a [FtMerchantRefund<'a>]),
+    FtTokensLocked(&'a [FtTokensLocked<'a>]),
+    FtPaymentDisputed(&'a [FtPaymentDisputed<'a>]),
+    FtDisputeResolved(&'a [FtDisputeResolved<'a>]),
+    FtStreamCreated(&'a [FtStreamCreated<'a>]),
+    FtStreamWithdrawn(&'a [FtStreamWithdrawn<'a>]),
+    FtStreamCancelled(&'a [FtStreamCancelled<'a>]),
+    FtDistributionCreated(&'a [FtDistributionCreated<'a>]),
+    FtDistributionClaimed(&'a [FtDistributionClaimed<'a>]),
+    FtSavingsWithdrawn(&'a [FtSavingsWithdrawn<'a>]),
+    FtDistributionSwept(&'a [FtDistributionSwept<'a>]),
+    FtRoscaRoundClosed(&'a [FtRoscaRoundClosed<'a>]),
+}
+
+/// The owner granted a role to an account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtRoleGranted<'a> {
+    pub account_id: &'a AccountId,
+    pub role: Role,
+}
+
+impl FtRoleGranted<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtRoleGranted(&[self]))
+    }
+}
+
+/// The owner revoked a role from an account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtRoleRevoked<'a> {
+    pub account_id: &'a AccountId,
+    pub role: Role,
+}
+
+impl FtRoleRevoked<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtRoleRevoked(&[self]))
+    }
+}
+
+/// A pauser suspended some features.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPaused<'a> {
+    pub account_id: &'a AccountId,
+    pub features: Vec<Feature>,
+}
+
+impl FtPaused<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtPaused(&[self]))
+    }
+}
+
+/// A pauser resumed some features.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtUnpaused<'a> {
+    pub account_id: &'a AccountId,
+    pub features: Vec<Feature>,
+}
+
+impl FtUnpaused<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtUnpaused(&[self]))
+    }
+}
+
+/// Tokens were seized from a frozen account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtSeize<'a> {
+    pub from: &'a AccountId,
+    pub to: &'a AccountId,
+    pub amount: &'a U128,
+    pub memo: &'a str,
+    pub seized_by: &'a AccountId,
+}
+
+impl FtSeize<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtSeize(&[self]))
+    }
+}
+
+/// The owner updated some fields of the token metadata.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMetadataUpdated<'a> {
+    pub owner_id: &'a AccountId,
+    pub fields: &'a [&'a str],
+}
+
+impl FtMetadataUpdated<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtMetadataUpdated(&[self]))
+    }
+}
+
+/// The owner pointed the metadata to a new off-chain document.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtReferenceUpdated<'a> {
+    pub owner_id: &'a AccountId,
+    pub reference: &'a str,
+    pub reference_hash: &'a Base64VecU8,
+}
+
+impl FtReferenceUpdated<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtReferenceUpdated(&[self]))
+    }
+}
+
+/// An owner's allowance for a spender changed to `amount`.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtApproval<'a> {
+    pub owner_id: &'a AccountId,
+    pub spender_id: &'a AccountId,
+    pub amount: &'a U128,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<&'a U64>,
+}
+
+impl FtApproval<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtApproval(&[self]))
+    }
+}
+
+/// An owner approved an operator or revoked its approval.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtOperatorApproval<'a> {
+    pub owner_id: &'a AccountId,
+    pub operator_id: &'a AccountId,
+    pub approved: bool,
+}
+
+impl FtOperatorApproval<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtOperatorApproval(&[self]))
+    }
+}
+
+/// The owner locked tokens in a vesting schedule.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtVestingCreated<'a> {
+    pub grant_id: u64,
+    pub account_id: &'a AccountId,
+    pub total: &'a U128,
+    pub start: U64,
+    pub cliff: U64,
+    pub end: U64,
+}
+
+impl FtVestingCreated<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtVestingCreated(&[self]))
+    }
+}
+
+/// A beneficiary claimed vested tokens.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtVestingClaimed<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtVestingClaimed<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtVestingClaimed(&[self]))
+    }
+}
+
+/// A customer paid a merchant.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMerchantPayment<'a> {
+    pub payment_id: U64,
+    pub merchant_id: &'a AccountId,
+    pub payer_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtMerchantPayment<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtMerchantPayment(&[self]))
+    }
+}
+
+/// A merchant's pending balance was settled to its payout account.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMerchantSettled<'a> {
+    pub merchant_id: &'a AccountId,
+    pub payout_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtMerchantSettled<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtMerchantSettled(&[self]))
+    }
+}
+
+/// The owner revoked a vesting grant, returning its unvested part to the treasury.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtVestingRevoked<'a> {
+    pub grant_id: u64,
+    pub account_id: &'a AccountId,
+    pub treasury_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtVestingRevoked<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtVestingRevoked(&[self]))
+    }
+}
+
+/// A merchant refunded (part of) a payment to its payer.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtMerchantRefund<'a> {
+    pub payment_id: U64,
+    pub merchant_id: &'a AccountId,
+    pub payer_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtMerchantRefund<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtMerchantRefund(&[self]))
+    }
+}
+
+/// A holder locked part of its balance until a given time.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtTokensLocked<'a> {
+    pub account_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub unlock_at: U64,
+}
+
+impl FtTokensLocked<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtTokensLocked(&[self]))
+    }
+}
+
+/// A payer disputed a merchant payment.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtPaymentDisputed<'a> {
+    pub payment_id: U64,
+    pub merchant_id: &'a AccountId,
+    pub payer_id: &'a AccountId,
+}
+
+impl FtPaymentDisputed<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtPaymentDisputed(&[self]))
+    }
+}
+
+/// An arbiter settled a disputed payment, reversing it or not.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDisputeResolved<'a> {
+    pub payment_id: U64,
+    pub merchant_id: &'a AccountId,
+    pub payer_id: &'a AccountId,
+    pub reversed: bool,
+    pub amount: &'a U128,
+}
+
+impl FtDisputeResolved<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtDisputeResolved(&[self]))
+    }
+}
+
+/// A sender escrowed tokens to stream to a receiver.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtStreamCreated<'a> {
+    pub stream_id: u64,
+    pub sender_id: &'a AccountId,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+    pub start: U64,
+    pub end: U64,
+}
+
+impl FtStreamCreated<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtStreamCreated(&[self]))
+    }
+}
+
+/// A receiver withdrew the streamed tokens.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtStreamWithdrawn<'a> {
+    pub stream_id: u64,
+    pub receiver_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtStreamWithdrawn<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtStreamWithdrawn(&[self]))
+    }
+}
+
+/// A stream was cancelled and its escrow split between the parties.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtStreamCancelled<'a> {
+    pub stream_id: u64,
+    pub cancelled_by: &'a AccountId,
+    pub receiver_amount: &'a U128,
+    pub sender_amount: &'a U128,
+}
+
+impl FtStreamCancelled<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtStreamCancelled(&[self]))
+    }
+}
+
+/// The owner locked tokens for a Merkle distribution.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDistributionCreated<'a> {
+    pub distribution_id: u64,
+    pub total: &'a U128,
+}
+
+impl FtDistributionCreated<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtDistributionCreated(&[self]))
+    }
+}
+
+/// An account claimed its allocation of a Merkle distribution.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDistributionClaimed<'a> {
+    pub distribution_id: u64,
+    pub account_id: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtDistributionClaimed<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtDistributionClaimed(&[self]))
+    }
+}
+
+/// A holder withdrew from a savings goal, paying a penalty if it was early.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtSavingsWithdrawn<'a> {
+    pub account_id: &'a AccountId,
+    pub name: &'a str,
+    pub amount: &'a U128,
+    pub penalty: &'a U128,
+}
+
+impl FtSavingsWithdrawn<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtSavingsWithdrawn(&[self]))
+    }
+}
+
+/// The owner recovered the unclaimed tokens of an expired distribution.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtDistributionSwept<'a> {
+    pub distribution_id: u64,
+    pub destination: &'a AccountId,
+    pub amount: &'a U128,
+}
+
+impl FtDistributionSwept<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtDistributionSwept(&[self]))
+    }
+}
+
+/// A period of a ROSCA group was closed and its pot paid out.
+#[must_use]
+#[derive(Serialize, Debug, Clone)]
+#[serde(crate = "near_sdk::serde")]
+pub struct FtRoscaRoundClosed<'a> {
+    pub group_id: u64,
+    pub round: u32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient_id: Option<&'a AccountId>,
+    pub amount: &'a U128,
+    pub defaulters: Vec<&'a AccountId>,
+}
+
+impl FtRoscaRoundClosed<'_> {
+    pub fn emit(self) {
+        emit(EventKind::FtRoscaRoundClosed(&[self]))
+    }
+}
+
+#[derive(Serialize, Debug)]
+#[serde(crate = "near_sdk::serde")]
+struct NearEvent<'a> {
+    standard: &'static str,
+    version: &'static str,
+    #[serde(flatten)]
+    event_kind: EventKind<'a>,
+}
+
+fn emit(event_kind: EventKind) {
+    let event = NearEvent { standard: EVENT_STANDARD, version: EVENT_STANDARD_VERSION, event_kind };
+    // Events cannot fail to serialize so fine to abort on error
+    let json = near_sdk::serde_json::to_string(&event).unwrap_or_else(|_| env::abort());
+    env::log_str(&format!("EVENT_JSON:{}", json));
+}
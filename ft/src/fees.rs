@@ -0,0 +1,353 @@
+//! Transfer fee.
+//!
+//! The owner can charge a fee, in basis points of the amount, on every move of tokens from one
+//! account to another: transfers, transfer calls, transfers made on a holder's behalf and
+//! sponsored transfers, as well as the tokens a sender escrows for someone else (pending and
+//! parked transfers, streams and merchant payments). The fee is moved from the sender to the fee
+//! collector as a separate transfer, subject to the blacklist and the whitelist, and the receiver
+//! gets the rest. When the receiver of a transfer call returns part of the amount, the same share
+//! of the fee goes back to the sender. Escrowed tokens are charged when they are escrowed, and the
+//! fee isn't returned if they go back to the sender. Owner airdrops, account migrations, transfers
+//! from or to the collector, and the contract's own holds and releases are not charged. The fee is
+//! capped at `MAX_FEE_BPS`.
+
+use near_contract_standards::fungible_token::receiver::ext_ft_receiver;
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Gas, PromiseOrValue,
+};
+
+use crate::types::TransferFee;
+use crate::utils::{pro_rata, pro_rata_balance};
+use crate::{Contract, ContractExt};
+
+/// Highest fee the owner can set, 5%.
+pub const MAX_FEE_BPS: u16 = 500;
+const BPS_DENOMINATOR: u16 = 10_000;
+
+const GAS_FOR_RESOLVE_FEE_TRANSFER: Gas = Gas(10_000_000_000_000);
+const GAS_FOR_FEE_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_FEE_TRANSFER.0);
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the transfer fee to `fee_bps` basis points. No fee is charged until a collector is set.
+    #[payable]
+    pub fn set_fee_bps(&mut self, fee_bps: u16) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(fee_bps <= MAX_FEE_BPS, format!("The fee can't exceed {} bps", MAX_FEE_BPS));
+        self.fee_bps = fee_bps;
+    }
+
+    /// Sets the account receiving the transfer fees, or stops charging fees if `None`. The
+    /// collector must be registered and can't be blacklisted.
+    #[payable]
+    pub fn set_fee_collector(&mut self, fee_collector: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(fee_collector) = fee_collector.as_ref() {
+            self.token.internal_unwrap_balance_of(fee_collector);
+            require!(!self.blacklist.contains(fee_collector), "The fee collector is blacklisted");
+        }
+        self.fee_collector = fee_collector;
+    }
+
+    pub fn transfer_fee(&self) -> TransferFee {
+        TransferFee { fee_bps: self.fee_bps, fee_collector: self.fee_collector.clone() }
+    }
+
+    /// Resolves a transfer call that was charged a fee: takes the unused amount back like
+    /// `ft_resolve_transfer`, and returns the same share of `fee` from `fee_collector_id` to the
+    /// sender. Returns the used amount.
+    #[private]
+    pub fn ft_resolve_fee_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        fee_collector_id: AccountId,
+        fee: U128,
+    ) -> U128 {
+        let used_amount = self.ft_resolve_transfer(sender_id.clone(), receiver_id, amount);
+        self.internal_refund_fee(
+            sender_id,
+            fee_collector_id,
+            fee.into(),
+            amount.into(),
+            amount.0 - used_amount.0,
+        );
+        used_amount
+    }
+}
+
+impl Contract {
+    /// Moves the fee on a transfer of `amount` from `sender_id` to `receiver_id` to the fee
+    /// collector and returns the amount left for the receiver.
+    pub(crate) fn internal_charge_fee(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) -> Balance {
+        let collector_id = match self.fee_collector.clone() {
+            Some(collector_id) if collector_id != *sender_id && collector_id != *receiver_id => {
+                collector_id
+            }
+            _ => return amount,
+        };
+        let fee = pro_rata(amount, self.fee_bps.into(), BPS_DENOMINATOR.into());
+        if fee > 0 {
+            require!(!self.blacklist.contains(&collector_id), "The fee collector is blacklisted");
+            require!(
+                self.internal_is_whitelisted(&collector_id),
+                "The fee collector is not whitelisted"
+            );
+            self.token.internal_transfer(sender_id, &collector_id, fee, Some("fee".to_string()));
+        }
+        amount - fee
+    }
+
+    /// Starts a transfer call whose `fee` was just charged, resolving it with
+    /// `ft_resolve_fee_transfer` so the fee is refunded along with the unused amount.
+    pub(crate) fn internal_fee_transfer_call(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        fee: Balance,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        assert_one_yocto();
+        require!(env::prepaid_gas() > GAS_FOR_FEE_TRANSFER_CALL, "More gas is required");
+        let fee_collector_id = self.fee_collector.clone().unwrap();
+        self.token.internal_transfer(&sender_id, &receiver_id, amount, memo);
+        let receiver_gas = env::prepaid_gas()
+            .0
+            .checked_sub(GAS_FOR_FEE_TRANSFER_CALL.0)
+            .unwrap_or_else(|| env::panic_str("Prepaid gas overflow"));
+        ext_ft_receiver::ext(receiver_id.clone())
+            .with_static_gas(receiver_gas.into())
+            .ft_on_transfer(sender_id.clone(), amount.into(), msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_FEE_TRANSFER)
+                    .ft_resolve_fee_transfer(
+                        sender_id,
+                        receiver_id,
+                        amount.into(),
+                        fee_collector_id,
+                        fee.into(),
+                    ),
+            )
+            .into()
+    }
+
+    /// Returns to `sender_id` the share of `fee` matching the `refunded` part of `amount`, as far
+    /// as the collector's balance allows. A sender that can't be credited gets it parked with the
+    /// rest of its refund.
+    pub(crate) fn internal_refund_fee(
+        &mut self,
+        sender_id: AccountId,
+        fee_collector_id: AccountId,
+        fee: Balance,
+        amount: Balance,
+        refunded: Balance,
+    ) {
+        let collector_balance = self.token.accounts.get(&fee_collector_id).unwrap_or(0);
+        let fee_refund = pro_rata_balance(fee, refunded, amount).min(collector_balance);
+        if fee_refund == 0 {
+            return;
+        }
+        let memo = Some("fee refund".to_string());
+        if self.token.accounts.contains_key(&sender_id) && !self.blacklist.contains(&sender_id) {
+            self.token.internal_transfer(&fee_collector_id, &sender_id, fee_refund, memo);
+        } else {
+            let contract_id = env::current_account_id();
+            self.token.internal_transfer(&fee_collector_id, &contract_id, fee_refund, memo);
+            self.internal_park_refund(sender_id, fee_refund);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, get_logs, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+    use crate::whitelist::TransferMode;
+
+    /// Registers accounts(1) and accounts(3) and charges a 1% fee collected by accounts(3).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_contract(&[accounts(1), accounts(3)]);
+        contract.set_fee_bps(100);
+        contract.set_fee_collector(Some(accounts(3)));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_with_fee() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 990);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 10);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 1000);
+        let logs = get_logs();
+        assert_eq!(logs.iter().filter(|log| log.contains(r#""event":"ft_transfer""#)).count(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "The fee can't exceed 500 bps")]
+    fn test_fee_above_cap() {
+        let (_, mut contract) = setup();
+        contract.set_fee_bps(MAX_FEE_BPS + 1);
+    }
+
+    fn assert_fee_collected(contract: &Contract) {
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 10);
+    }
+
+    #[test]
+    fn test_fee_on_pending_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_transfer_confirmation(Some(1_000.into()));
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.ft_transfer(accounts(1), 1000.into(), None);
+        assert_eq!(contract.pending_transfer(0).unwrap().amount.0, 990);
+        assert_fee_collected(&contract);
+    }
+
+    #[test]
+    fn test_fee_on_sponsored_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(contract.storage_balance_bounds().min.0).build());
+        contract.ft_sponsored_transfer(accounts(4), 1000.into(), None, None);
+        assert_eq!(contract.ft_balance_of(accounts(4)).0, 990);
+        assert_fee_collected(&contract);
+    }
+
+    #[test]
+    fn test_fee_on_stream() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.create_stream(accounts(1), 1000.into(), 0.into(), 1_000.into());
+        assert_eq!(contract.stream(0).unwrap().amount.0, 990);
+        assert_fee_collected(&contract);
+    }
+
+    #[test]
+    fn test_fee_on_merchant_payment() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 2000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.register_merchant(accounts(1), 1_000.into());
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_pay_merchant(accounts(1), 1000.into(), None);
+        assert_eq!(contract.merchant_payment(0.into()).unwrap().amount.0, 990);
+        assert_fee_collected(&contract);
+    }
+
+    #[test]
+    fn test_fee_on_parked_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.ft_transfer_or_park(accounts(4), 1000.into(), None);
+        assert_eq!(contract.pending_credit(0).unwrap().amount.0, 990);
+        assert_fee_collected(&contract);
+    }
+
+    #[test]
+    fn test_no_fee_on_migration() {
+        let (mut context, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 1000.into(), None);
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.accept_migration(Some(accounts(1)));
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.migrate_account(accounts(2));
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 1000 + 990);
+        assert_fee_collected(&contract);
+    }
+
+    #[test]
+    fn test_no_fee_on_airdrop() {
+        let (_, mut contract) = setup();
+        contract.airdrop(vec![(accounts(1), 1000.into())]);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1000);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The fee collector is blacklisted")]
+    fn test_fee_to_blacklisted_collector() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.add_to_blacklist(accounts(3));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), 1000.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The fee collector is blacklisted")]
+    fn test_set_blacklisted_fee_collector() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.add_to_blacklist(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_fee_collector(Some(accounts(1)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The fee collector is not whitelisted")]
+    fn test_fee_to_collector_outside_whitelist() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.add_to_whitelist(accounts(1));
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_transfer_mode(TransferMode::WhitelistOnly);
+        contract.ft_transfer(accounts(1), 1000.into(), None);
+    }
+
+    #[test]
+    fn test_fee_refunded_with_unused_amount() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.prepaid_gas(Gas(100_000_000_000_000)).build());
+        contract.ft_transfer_call(accounts(1), 1000.into(), None, String::new());
+        assert_fee_collected(&contract);
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Successful(b"\"495\"".to_vec())]
+        );
+        let used = contract.ft_resolve_fee_transfer(
+            accounts(2),
+            accounts(1),
+            990.into(),
+            accounts(3),
+            10.into(),
+        );
+        assert_eq!(used.0, 495);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 495);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 5);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 500);
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-account freezes.
+//!
+//! Accounts with the compliance role can freeze an account entirely or freeze part of its
+//! balance. A frozen account can still receive tokens, but only its unfrozen remainder can leave
+//! it, by transfer or by burn, and an account with frozen funds can't be closed.
+
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::roles::{require_role, Role};
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Freezes `amount` of the balance of `account_id`, or the whole account when `None` is
+    /// passed, replacing any previous freeze. The attached deposit pays for storing the freeze.
+    #[payable]
+    pub fn freeze_account(&mut self, account_id: AccountId, amount: Option<U128>) {
+        require_role!(self, Role::Compliance);
+        require!(account_id != env::current_account_id(), "The contract's account can't be frozen");
+        if let Some(amount) = amount {
+            require!(amount.0 > 0, "The amount should be a positive number");
+        }
+        let initial_storage_usage = env::storage_usage();
+        self.frozen_accounts.insert(&account_id, &amount.map(|amount| amount.0));
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    #[payable]
+    pub fn unfreeze_account(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        require_role!(self, Role::Compliance);
+        let initial_storage_usage = env::storage_usage();
+        require!(self.frozen_accounts.remove(&account_id).is_some(), "The account is not frozen");
+        refund_released_storage(
+            env::predecessor_account_id(),
+            initial_storage_usage - env::storage_usage(),
+        );
+    }
+
+    /// Returns how much of the balance of `account_id` can't be moved.
+    pub fn frozen_balance_of(&self, account_id: AccountId) -> U128 {
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        match self.frozen_accounts.get(&account_id) {
+            None => U128(0),
+            Some(None) => U128(balance),
+            Some(Some(frozen)) => U128(frozen.min(balance)),
+        }
+    }
+}
+
+impl Contract {
+    /// Panics if `amount` can't leave `account_id` because of a freeze.
+    pub(crate) fn internal_assert_unfrozen(&self, account_id: &AccountId, amount: Balance) {
+        match self.frozen_accounts.get(account_id) {
+            None => {}
+            Some(None) => env::panic_str("The account is frozen"),
+            Some(Some(frozen)) => {
+                let balance = self.token.accounts.get(account_id).unwrap_or(0);
+                require!(
+                    amount <= balance.saturating_sub(frozen),
+                    "The amount exceeds the account's unfrozen balance"
+                );
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Registers accounts(1) and freezes `amount` of the owner's balance.
+    fn setup(amount: Option<U128>) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.freeze_account(accounts(2), amount);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_unfrozen_remainder() {
+        let (_, mut contract) = setup(Some(U128(TOTAL_SUPPLY - 100)));
+        assert_eq!(contract.frozen_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the account's unfrozen balance")]
+    fn test_transfer_frozen_funds() {
+        let (_, mut contract) = setup(Some(U128(TOTAL_SUPPLY - 100)));
+        contract.ft_transfer(accounts(1), 101.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account is frozen")]
+    fn test_transfer_from_frozen_account() {
+        let (_, mut contract) = setup(None);
+        assert_eq!(contract.frozen_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        contract.ft_transfer(accounts(1), 1.into(), None);
+    }
+
+    #[test]
+    fn test_unfreeze_account() {
+        let (_, mut contract) = setup(None);
+        contract.unfreeze_account(accounts(2));
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.frozen_balance_of(accounts(2)).0, 0);
+    }
+}
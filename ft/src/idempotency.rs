@@ -0,0 +1,118 @@
+//! Client-supplied idempotency keys for transfers.
+//!
+//! Custodians retrying a transaction they haven't seen finalize can't tell whether the first
+//! attempt went through. `ft_transfer_with_key` records the key of every transfer for
+//! `IDEMPOTENCY_KEY_RETENTION`, and a transfer reusing a recorded key of the same sender within
+//! that window is rejected instead of moving the tokens twice. The keys are stored per sender and
+//! the expired ones are dropped whenever the sender uses a new key, so a sender has at most
+//! `MAX_LIVE_KEYS` keys at a time. The attached deposit pays for storing the key, and the storage
+//! of the dropped keys is refunded to the sender.
+
+use std::convert::TryInto;
+
+use near_sdk::json_types::U128;
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// How long a key is remembered, in nanoseconds.
+const IDEMPOTENCY_KEY_RETENTION: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_IDEMPOTENCY_KEY_LEN: usize = 64;
+/// Maximum number of unexpired keys per sender.
+const MAX_LIVE_KEYS: usize = 100;
+
+#[near_bindgen]
+impl Contract {
+    /// Same as `ft_transfer`, but rejected if the caller already made a transfer with the same
+    /// `idempotency_key` within the retention window. Requires at least 1 yoctoNEAR attached,
+    /// plus the storage cost of the key the first time it is used.
+    #[payable]
+    pub fn ft_transfer_with_key(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        idempotency_key: String,
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(
+            !idempotency_key.is_empty() && idempotency_key.len() <= MAX_IDEMPOTENCY_KEY_LEN,
+            "Invalid idempotency key"
+        );
+        let sender_id = env::predecessor_account_id();
+        let key: [u8; 32] = env::sha256(idempotency_key.as_bytes()).try_into().unwrap();
+        let now = env::block_timestamp();
+        let mut keys = self.idempotency_keys.get(&sender_id).unwrap_or_default();
+        keys.retain(|(_, expires_at)| *expires_at > now);
+        require!(
+            keys.iter().all(|(used_key, _)| *used_key != key),
+            "The idempotency key was already used"
+        );
+        require!(keys.len() < MAX_LIVE_KEYS, "Too many unexpired idempotency keys");
+        keys.push((key, now + IDEMPOTENCY_KEY_RETENTION));
+        let initial_storage_usage = env::storage_usage();
+        self.idempotency_keys.insert(&sender_id, &keys);
+        let storage_usage = env::storage_usage();
+        if storage_usage < initial_storage_usage {
+            refund_released_storage(sender_id.clone(), initial_storage_usage - storage_usage);
+        }
+        let transfer_storage =
+            self.internal_ft_transfer(sender_id, receiver_id, amount.into(), memo);
+        refund_deposit(storage_usage.saturating_sub(initial_storage_usage) + transfer_storage);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Registers accounts(1) and makes a transfer to it with the key "abc".
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.ft_transfer_with_key(accounts(1), 100.into(), None, "abc".to_string());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The idempotency key was already used")]
+    fn test_replayed_key() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer_with_key(accounts(1), 100.into(), None, "abc".to_string());
+    }
+
+    #[test]
+    fn test_key_reusable_after_retention() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(IDEMPOTENCY_KEY_RETENTION)
+            .build());
+        contract.ft_transfer_with_key(accounts(1), 100.into(), None, "abc".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 200);
+    }
+
+    #[test]
+    fn test_deposit_refunded_when_keys_are_pruned() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .block_timestamp(IDEMPOTENCY_KEY_RETENTION)
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .build());
+        contract.ft_transfer_with_key(accounts(1), 100.into(), None, "def".to_string());
+        let refund = get_created_receipts().pop().unwrap();
+        assert_eq!(refund.receiver_id, accounts(2));
+        assert!(matches!(
+            refund.actions[..],
+            [VmAction::Transfer { deposit }] if deposit == env::storage_byte_cost() * 1000
+        ));
+    }
+}
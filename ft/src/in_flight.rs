@@ -0,0 +1,209 @@
+//! Opt-in in-flight accounting for `ft_transfer_call`.
+//!
+//! By default `ft_transfer_call` credits the receiver before calling its `ft_on_transfer`, and
+//! takes the unused amount back in `ft_transfer_call`'s resolve step. A sender that enables the
+//! in-flight mode gets a different flow: the amount is held by the contract and shown by
+//! `ft_in_flight_of` while the receiver runs, and the resolve step then credits the receiver with
+//! the used part and returns the rest to the sender. Receivers that spend the tokens inside
+//! `ft_on_transfer` don't work with this mode, which is why it is opt-in.
+//!
+//! The sender's entry doubles as its opt-in, so the deposit attached when enabling the mode pays
+//! for tracking its in-flight amount.
+
+use near_contract_standards::fungible_token::receiver::ext_ft_receiver;
+use near_sdk::json_types::U128;
+use near_sdk::{
+    assert_one_yocto, env, near_bindgen, require, AccountId, Balance, Gas, PromiseOrValue,
+    PromiseResult,
+};
+
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_RESOLVE_IN_FLIGHT: Gas = Gas(5_000_000_000_000);
+const GAS_FOR_IN_FLIGHT_TRANSFER_CALL: Gas = Gas(25_000_000_000_000 + GAS_FOR_RESOLVE_IN_FLIGHT.0);
+
+#[near_bindgen]
+impl Contract {
+    /// Enables or disables the in-flight mode for the caller's transfer calls. The attached
+    /// deposit pays for tracking the in-flight amount.
+    #[payable]
+    pub fn set_in_flight_mode(&mut self, enabled: bool) {
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        if enabled {
+            require!(!self.in_flight.contains_key(&account_id), "The in-flight mode is already on");
+            self.in_flight.insert(&account_id, &0);
+            refund_deposit(env::storage_usage() - initial_storage_usage);
+        } else {
+            assert_one_yocto();
+            let in_flight = self
+                .in_flight
+                .get(&account_id)
+                .unwrap_or_else(|| env::panic_str("The in-flight mode is not on"));
+            require!(in_flight == 0, "Wait for the in-flight transfer calls to settle");
+            self.in_flight.remove(&account_id);
+            refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+        }
+    }
+
+    /// Returns the amount of `account_id`'s transfer calls waiting for their receivers.
+    pub fn ft_in_flight_of(&self, account_id: AccountId) -> U128 {
+        U128(self.in_flight.get(&account_id).unwrap_or(0))
+    }
+
+    /// Settles an in-flight transfer call: credits the receiver with the amount it used and
+    /// returns the rest to the sender, along with the same share of the `fee` the call was charged.
+    /// Returns the used amount.
+    #[private]
+    pub fn ft_resolve_in_flight(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        fee_collector_id: Option<AccountId>,
+        fee: U128,
+    ) -> U128 {
+        let amount: Balance = amount.into();
+        let unused_amount = match env::promise_result(0) {
+            PromiseResult::NotReady => env::abort(),
+            PromiseResult::Successful(value) => near_sdk::serde_json::from_slice::<U128>(&value)
+                .map_or(amount, |unused_amount| amount.min(unused_amount.0)),
+            PromiseResult::Failed => amount,
+        };
+        // A receiver that closed its account in the meantime can't be credited.
+        let used_amount =
+            if self.token.accounts.contains_key(&receiver_id) { amount - unused_amount } else { 0 };
+        if let Some(in_flight) = self.in_flight.get(&sender_id) {
+            self.in_flight.insert(&sender_id, &in_flight.saturating_sub(amount));
+        }
+
+        let contract_id = env::current_account_id();
+        if used_amount > 0 {
+            self.token.internal_transfer(&contract_id, &receiver_id, used_amount, None);
+        }
+        let refund = amount - used_amount;
+        if refund > 0 {
+            if self.token.accounts.contains_key(&sender_id) && !self.blacklist.contains(&sender_id)
+            {
+                self.token.internal_transfer(&contract_id, &sender_id, refund, None);
+            } else {
+                self.internal_park_refund(sender_id.clone(), refund);
+            }
+        }
+        if let Some(fee_collector_id) = fee_collector_id {
+            self.internal_refund_fee(sender_id, fee_collector_id, fee.into(), amount, refund);
+        }
+        used_amount.into()
+    }
+}
+
+impl Contract {
+    /// Holds `amount` for a transfer call of a sender in the in-flight mode and calls the
+    /// receiver. `fee` is what the call was charged, refunded in part if the receiver doesn't use
+    /// the whole amount.
+    pub(crate) fn internal_in_flight_transfer_call(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        fee: Balance,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        require!(env::prepaid_gas() > GAS_FOR_IN_FLIGHT_TRANSFER_CALL, "More gas is required");
+        require!(sender_id != receiver_id, "Sender and receiver should be different");
+        require!(amount > 0, "The amount should be a positive number");
+        self.token.internal_unwrap_balance_of(&receiver_id);
+        self.internal_hold(
+            &sender_id,
+            amount,
+            memo.as_deref().unwrap_or("in-flight transfer call"),
+        );
+        let in_flight = self.in_flight.get(&sender_id).unwrap_or(0);
+        self.in_flight.insert(&sender_id, &(in_flight + amount));
+
+        let receiver_gas = env::prepaid_gas()
+            .0
+            .checked_sub(GAS_FOR_IN_FLIGHT_TRANSFER_CALL.0)
+            .unwrap_or_else(|| env::panic_str("Prepaid gas overflow"));
+        ext_ft_receiver::ext(receiver_id.clone())
+            .with_static_gas(receiver_gas.into())
+            .ft_on_transfer(sender_id.clone(), amount.into(), msg)
+            .then(
+                Self::ext(env::current_account_id())
+                    .with_static_gas(GAS_FOR_RESOLVE_IN_FLIGHT)
+                    .ft_resolve_in_flight(
+                        sender_id,
+                        receiver_id,
+                        amount.into(),
+                        self.fee_collector.clone().filter(|_| fee > 0),
+                        fee.into(),
+                    ),
+            )
+            .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Registers accounts(1), enables the in-flight mode for accounts(2) and starts a transfer
+    /// call of 100 tokens to accounts(1).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.set_in_flight_mode(true);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .prepaid_gas(Gas(100_000_000_000_000))
+            .build());
+        contract.ft_transfer_call(accounts(1), 100.into(), None, String::new());
+        (context, contract)
+    }
+
+    fn resolve(context: &mut VMContextBuilder, contract: &mut Contract, result: PromiseResult) {
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![result]
+        );
+        contract.ft_resolve_in_flight(accounts(2), accounts(1), 100.into(), None, 0.into());
+    }
+
+    #[test]
+    fn test_in_flight_amount_is_held() {
+        let (_, contract) = setup();
+        assert_eq!(contract.ft_in_flight_of(accounts(2)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+    }
+
+    #[test]
+    fn test_resolve_partially_used() {
+        let (mut context, mut contract) = setup();
+        let unused = near_sdk::serde_json::to_vec(&U128(30)).unwrap();
+        resolve(&mut context, &mut contract, PromiseResult::Successful(unused));
+        assert_eq!(contract.ft_in_flight_of(accounts(2)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 70);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 70);
+    }
+
+    #[test]
+    fn test_resolve_failed_call() {
+        let (mut context, mut contract) = setup();
+        resolve(&mut context, &mut contract, PromiseResult::Failed);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+    }
+}
@@ -0,0 +1,104 @@
+//! Opt-in allowlist of senders.
+//!
+//! An account can restrict who may transfer tokens to it, so it can't be dusted or targeted by
+//! address-poisoning transfers from look-alike accounts. Transfers from senders that are not on
+//! the receiver's allowlist are rejected. Tokens the contract returns or releases to the account
+//! (e.g. refunds, accepted pending transfers) are not affected.
+
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of senders on a single allowlist.
+const MAX_ALLOWED_SENDERS: usize = 50;
+
+#[near_bindgen]
+impl Contract {
+    /// Only accepts transfers to the caller from `sender_ids`, or accepts transfers from anyone
+    /// again when `None` is passed. The attached deposit pays for storing the allowlist.
+    #[payable]
+    pub fn set_inbound_allowlist(&mut self, sender_ids: Option<Vec<AccountId>>) {
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        match sender_ids {
+            Some(sender_ids) => {
+                require!(sender_ids.len() <= MAX_ALLOWED_SENDERS, "Too many allowed senders");
+                self.inbound_allowlists.insert(&account_id, &sender_ids);
+                let storage_usage = env::storage_usage();
+                if storage_usage > initial_storage_usage {
+                    refund_deposit(storage_usage - initial_storage_usage);
+                } else {
+                    refund_released_storage(account_id, initial_storage_usage - storage_usage);
+                }
+            }
+            None => {
+                assert_one_yocto();
+                self.inbound_allowlists.remove(&account_id);
+                refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+            }
+        }
+    }
+
+    /// Returns the senders `account_id` accepts transfers from, or `None` if it accepts them from
+    /// anyone.
+    pub fn inbound_allowlist_of(&self, account_id: AccountId) -> Option<Vec<AccountId>> {
+        self.inbound_allowlists.get(&account_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_assert_accepts_from(
+        &self,
+        receiver_id: &AccountId,
+        sender_id: &AccountId,
+    ) {
+        if let Some(sender_ids) = self.inbound_allowlists.get(receiver_id) {
+            require!(
+                sender_ids.contains(sender_id),
+                "The receiver doesn't accept transfers from this sender"
+            );
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Registers accounts(1), which only accepts transfers from the owner accounts(2).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_inbound_allowlist(Some(vec![accounts(2)]));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The receiver doesn't accept transfers from this sender")]
+    fn test_transfer_from_unknown_sender() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+    }
+
+    #[test]
+    fn test_transfer_from_allowed_sender() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+    }
+}
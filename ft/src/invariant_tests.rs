@@ -0,0 +1,171 @@
+//! Property-based tests running random sequences of operations against the contract.
+//!
+//! The operations are registrations, unregistrations (forced ones burn the remaining balance),
+//! transfers, mints and burns. Operations that the contract would reject are skipped, since a panic in
+//! the mocked blockchain doesn't revert the state written before it. After every step the
+//! harness checks that:
+//! - the balances of all accounts add up to the total supply,
+//! - no account holds more than the total supply,
+//! - the NEAR refunded by the contract never exceeds the NEAR deposited for storage.
+
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::mock::VmAction;
+use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+use near_sdk::{
+    env, testing_env, AccountId, Balance, MockedBlockchain, RuntimeFeesConfig, VMConfig,
+};
+use proptest::prelude::*;
+
+use crate::tests::{get_context, TOTAL_SUPPLY};
+use crate::Contract;
+
+/// Number of accounts besides the contract taking part in the operations. accounts(1) is the
+/// owner.
+const NUM_ACCOUNTS: usize = 5;
+
+#[derive(Debug, Clone)]
+enum Op {
+    Register(usize),
+    Unregister { account: usize, force: bool },
+    Transfer { sender: usize, receiver: usize, amount: Balance },
+    Mint { account: usize, amount: Balance },
+    Burn { account: usize, amount: Balance },
+}
+
+fn op() -> impl Strategy<Value = Op> {
+    let account = 1..=NUM_ACCOUNTS;
+    prop_oneof![
+        account.clone().prop_map(Op::Register),
+        (account.clone(), any::<bool>())
+            .prop_map(|(account, force)| Op::Unregister { account, force }),
+        (account.clone(), account.clone(), 1..TOTAL_SUPPLY)
+            .prop_map(|(sender, receiver, amount)| Op::Transfer { sender, receiver, amount }),
+        (account.clone(), 1..TOTAL_SUPPLY)
+            .prop_map(|(account, amount)| Op::Mint { account, amount }),
+        (account, 1..TOTAL_SUPPLY).prop_map(|(account, amount)| Op::Burn { account, amount }),
+    ]
+}
+
+struct Harness {
+    context: VMContextBuilder,
+    contract: Contract,
+    deposited: Balance,
+    refunded: Balance,
+}
+
+impl Harness {
+    fn new() -> Self {
+        let context = get_context(accounts(1));
+        // Start every case from empty storage, which `testing_env!` would carry over.
+        env::set_blockchain_interface(MockedBlockchain::new(
+            context.build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            vec![],
+            Default::default(),
+            Default::default(),
+            None,
+        ));
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
+        // The owner is registered by `new`, as if it had paid the minimum storage balance.
+        let deposited = contract.storage_balance_bounds().min.into();
+        Self { context, contract, deposited, refunded: 0 }
+    }
+
+    fn set_caller(&mut self, account_id: AccountId, deposit: Balance) {
+        testing_env!(self
+            .context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(account_id)
+            .attached_deposit(deposit)
+            .build());
+        self.deposited += deposit;
+    }
+
+    fn is_registered(&self, account_id: &AccountId) -> bool {
+        self.contract.storage_balance_of(account_id.clone()).is_some()
+    }
+
+    fn apply(&mut self, op: Op) {
+        match op {
+            Op::Register(i) => {
+                if self.is_registered(&accounts(i)) {
+                    return;
+                }
+                let min_deposit = self.contract.storage_balance_bounds().min.into();
+                self.set_caller(accounts(i), min_deposit);
+                self.contract.storage_deposit(None, None);
+            }
+            Op::Unregister { account, force } => {
+                let account_id = accounts(account);
+                if !self.is_registered(&account_id)
+                    || (!force && self.contract.ft_balance_of(account_id.clone()).0 > 0)
+                {
+                    return;
+                }
+                self.set_caller(account_id, 1);
+                assert!(self.contract.storage_unregister(Some(force)));
+            }
+            Op::Transfer { sender, receiver, amount } => {
+                let (sender_id, receiver_id) = (accounts(sender), accounts(receiver));
+                if sender == receiver
+                    || !self.is_registered(&sender_id)
+                    || !self.is_registered(&receiver_id)
+                    || self.contract.ft_balance_of(sender_id.clone()).0 < amount
+                {
+                    return;
+                }
+                self.set_caller(sender_id, 1);
+                self.contract.ft_transfer(receiver_id, amount.into(), None);
+            }
+            Op::Mint { account, amount } => {
+                let account_id = accounts(account);
+                if !self.is_registered(&account_id)
+                    || self.contract.ft_total_supply().0.checked_add(amount).is_none()
+                {
+                    return;
+                }
+                self.set_caller(accounts(1), 1);
+                self.contract.ft_mint(account_id, amount.into(), None);
+            }
+            Op::Burn { account, amount } => {
+                let account_id = accounts(account);
+                if !self.is_registered(&account_id)
+                    || self.contract.ft_balance_of(account_id.clone()).0 < amount
+                {
+                    return;
+                }
+                self.set_caller(account_id, 1);
+                self.contract.ft_burn(amount.into(), None);
+            }
+        }
+        for receipt in get_created_receipts() {
+            for action in receipt.actions {
+                if let VmAction::Transfer { deposit } = action {
+                    self.refunded += deposit;
+                }
+            }
+        }
+    }
+
+    fn check_invariants(&self) {
+        let total_supply = self.contract.ft_total_supply().0;
+        let balances: Vec<Balance> =
+            (0..=NUM_ACCOUNTS).map(|i| self.contract.ft_balance_of(accounts(i)).0).collect();
+        assert_eq!(balances.iter().sum::<Balance>(), total_supply);
+        assert!(balances.iter().all(|balance| *balance <= total_supply));
+        assert!(self.refunded <= self.deposited);
+    }
+}
+
+proptest! {
+    #[test]
+    fn test_invariants_hold(ops in prop::collection::vec(op(), 1..50)) {
+        let mut harness = Harness::new();
+        for op in ops {
+            harness.apply(op);
+            harness.check_invariants();
+        }
+    }
+}
@@ -3,8 +3,8 @@ Fungible Token implementation with JSON serialization.
 NOTES:
   - The maximum balance value is limited by U128 (2**128 - 1).
   - JSON calls should pass U128 as a base-10 string. E.g. "100".
-  - The contract optimizes the inner trie structure by hashing account IDs. It will prevent some
-    abuse of deep tries. Shouldn't be an issue, once NEAR clients implement full hashing of keys.
+  - Account balances are stored under un-hashed keys (a prefix followed by the Borsh-encoded
+    account ID), so they can be checked against state proofs. See `raw_state` for the layout.
   - The contract tracks the change in storage before and after the call. If the storage increases,
     the contract requires the caller of the contract to attach enough deposit to the function call
     to cover the storage cost.
@@ -15,24 +15,209 @@ NOTES:
   - To prevent the deployed contract from being modified or deleted, it should not have any access
     keys on its account.
 */
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
 use near_contract_standards::fungible_token::metadata::{
     FungibleTokenMetadata, FungibleTokenMetadataProvider, FT_METADATA_SPEC,
 };
+use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
 use near_contract_standards::fungible_token::FungibleToken;
+use near_contract_standards::storage_management::{
+    StorageBalance, StorageBalanceBounds, StorageManagement,
+};
 use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
-use near_sdk::collections::LazyOption;
+use near_sdk::collections::{LazyOption, LookupMap, LookupSet, Vector};
 use near_sdk::json_types::U128;
-use near_sdk::{env, log, near_bindgen, AccountId, Balance, PanicOnDefault, PromiseOrValue};
+use near_sdk::{
+    assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, PanicOnDefault, Promise,
+    PromiseOrValue, StorageUsage,
+};
+
+mod activity;
+mod airdrop;
+mod allowances;
+mod approval_freeze;
+mod balance_proof;
+mod blacklist;
+mod chargebacks;
+mod confirmation;
+mod credits;
+mod custody;
+mod display_handles;
+mod distributor;
+mod events;
+mod fees;
+mod freeze;
+mod idempotency;
+mod in_flight;
+mod inbound_allowlist;
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod invariant_tests;
+mod lockups;
+mod merchants;
+mod metadata;
+mod migration;
+mod notifications;
+mod operators;
+mod owner;
+mod pause;
+mod payment_requests;
+mod permit;
+mod policy;
+mod raw_state;
+mod refund_claims;
+#[cfg(all(feature = "replay", not(target_arch = "wasm32")))]
+pub mod replay;
+mod roles;
+mod rosca;
+mod savings;
+mod seize;
+mod sponsor;
+mod state_migration;
+mod streams;
+mod supply;
+mod sweep;
+mod types;
+mod utils;
+mod vesting;
+mod whitelist;
+
+use activity::AccountActivity;
+use allowances::Allowance;
+use confirmation::{PendingTransfer, TransferConfirmation};
+use credits::PendingCredit;
+use custody::Custody;
+use distributor::Distribution;
+use lockups::Lockup;
+use merchants::{Merchant, MerchantPayment};
+use operators::OperatorApproval;
+use pause::{Feature, PausedFeatures};
+use permit::PermitKey;
+use policy::{QueuedTransfer, TransferPolicy};
+use raw_state::{METADATA_PREFIX, TOKEN_PREFIX};
+use refund_claims::RefundClaim;
+use roles::RoleSet;
+use rosca::Rosca;
+use savings::SavingsGoal;
+use seize::Seizure;
+use sponsor::StorageSponsor;
+use streams::Stream;
+use utils::refund_deposit;
+use vesting::VestingSchedule;
+use whitelist::TransferMode;
 
 #[near_bindgen]
 #[derive(BorshDeserialize, BorshSerialize, PanicOnDefault)]
 pub struct Contract {
     token: FungibleToken,
     metadata: LazyOption<FungibleTokenMetadata>,
+    /// Account allowed to manage the token, e.g. to mint new tokens.
+    owner_id: AccountId,
+    /// Account proposed as the next owner, until it accepts.
+    pending_owner_id: Option<AccountId>,
+    /// Total supply that minting can never exceed, if the token is capped.
+    max_supply: Option<Balance>,
+    /// Referral tag -> dApp paying the storage of accounts registered with that tag.
+    storage_sponsors: LookupMap<String, StorageSponsor>,
+    /// Account registered with a referral tag -> the tag whose sponsor paid its registration.
+    sponsored_accounts: LookupMap<AccountId, String>,
+    /// Account -> soft limit above which its transfers wait for the approver.
+    transfer_policies: LookupMap<AccountId, TransferPolicy>,
+    /// Account -> its transfers waiting for approval.
+    queued_transfers: LookupMap<AccountId, Vec<QueuedTransfer>>,
+    next_queued_transfer_id: u64,
+    /// Account -> how long its incoming transfers wait for acceptance.
+    transfer_confirmations: LookupMap<AccountId, TransferConfirmation>,
+    /// Transfers held by the contract until the receiver accepts them.
+    pending_transfers: LookupMap<u64, PendingTransfer>,
+    next_pending_transfer_id: u64,
+    /// Owner -> operators allowed to move the owner's tokens.
+    operators: LookupMap<AccountId, Vec<OperatorApproval>>,
+    /// New account -> old account whose migration into it was accepted.
+    migration_consents: LookupMap<AccountId, AccountId>,
+    /// Receiver -> contract notified about its incoming plain transfers.
+    deposit_notifiers: LookupMap<AccountId, AccountId>,
+    /// Sender -> hashes of its idempotency keys and the block timestamps until which they can't
+    /// be reused.
+    idempotency_keys: LookupMap<AccountId, Vec<([u8; 32], u64)>>,
+    /// Owner -> when its frozen approvals can be unfrozen, if an unfreeze was requested.
+    approval_freezes: LookupMap<AccountId, Option<u64>>,
+    /// Account -> short handle shown next to it in transfer events.
+    display_handles: LookupMap<AccountId, String>,
+    /// Unregistered sender -> refunds of its transfer calls waiting for it to register again.
+    refund_claims: LookupMap<AccountId, RefundClaim>,
+    /// Account receiving the expired refund claims, the owner when `None`.
+    refund_recovery_id: Option<AccountId>,
+    /// Transfers to unregistered accounts, claimable once the receiver registers.
+    pending_credits: LookupMap<u64, PendingCredit>,
+    next_pending_credit_id: u64,
+    /// Account -> when it last sent and received tokens.
+    account_activity: LookupMap<AccountId, AccountActivity>,
+    /// Receiver -> the only senders it accepts transfers from.
+    inbound_allowlists: LookupMap<AccountId, Vec<AccountId>>,
+    /// Account -> the roles it holds.
+    roles: LookupMap<AccountId, RoleSet>,
+    /// Features suspended by a pauser.
+    paused: PausedFeatures,
+    /// Accounts that can neither send nor receive tokens.
+    blacklist: LookupSet<AccountId>,
+    /// Whether only whitelisted accounts can receive tokens.
+    transfer_mode: TransferMode,
+    whitelist: LookupSet<AccountId>,
+    /// Account -> the amount of its balance that can't leave it, or `None` if it is frozen entirely.
+    frozen_accounts: LookupMap<AccountId, Option<Balance>>,
+    /// Audit log of the funds seized from frozen accounts.
+    seizures: Vector<Seizure>,
+    /// (owner, spender) -> amount the spender can still move with `ft_transfer_from`.
+    allowances: LookupMap<(AccountId, AccountId), Allowance>,
+    /// Owner -> spenders it has an allowance for.
+    allowance_spenders: LookupMap<AccountId, Vec<AccountId>>,
+    /// Spender -> owners that gave it an allowance.
+    allowance_owners: LookupMap<AccountId, Vec<AccountId>>,
+    /// Account -> the key that signs its permits and the nonce of its next permit.
+    permit_keys: LookupMap<AccountId, PermitKey>,
+    /// Sender in the in-flight mode -> amount of its transfer calls waiting for the receivers.
+    in_flight: LookupMap<AccountId, Balance>,
+    /// Beneficiary -> its vesting schedules.
+    vestings: LookupMap<AccountId, Vec<VestingSchedule>>,
+    /// Merchant -> its payout settings, pending balance and sales report.
+    merchants: LookupMap<AccountId, Merchant>,
+    /// Payments received by merchants, by id.
+    merchant_payments: LookupMap<u64, MerchantPayment>,
+    next_merchant_payment_id: u64,
+    next_vesting_grant_id: u64,
+    /// Account receiving the unvested part of revoked grants, the owner when `None`.
+    vesting_treasury_id: Option<AccountId>,
+    /// Account -> the part of its balance it locked until a given time.
+    lockups: LookupMap<AccountId, Lockup>,
+    /// Payment streams, by id.
+    streams: LookupMap<u64, Stream>,
+    /// Sender -> ids of its streams.
+    streams_by_sender: LookupMap<AccountId, Vec<u64>>,
+    /// Receiver -> ids of its streams.
+    streams_by_receiver: LookupMap<AccountId, Vec<u64>>,
+    next_stream_id: u64,
+    /// Ward -> its guardian, release time and spend limit.
+    custodies: LookupMap<AccountId, Custody>,
+    /// Merkle distributions, by id.
+    distributions: LookupMap<u64, Distribution>,
+    next_distribution_id: u64,
+    /// (distribution, leaf index / 64) -> bitmap of the claimed leaves.
+    claimed_leaves: LookupMap<(u64, u64), u64>,
+    /// Account -> its savings goals.
+    savings_goals: LookupMap<AccountId, Vec<SavingsGoal>>,
+    /// ROSCA groups, by id.
+    roscas: LookupMap<u64, Rosca>,
+    next_rosca_id: u64,
+    /// Transfer fee in basis points, charged while a fee collector is set.
+    fee_bps: u16,
+    fee_collector: Option<AccountId>,
 }
 
 const DATA_IMAGE_SVG_NEAR_ICON: &str = "data:image/svg+xml,%3C%3Fxml%20version%3D%221.0%22%20encoding%3D%22UTF-8%22%20standalone%3D%22no%22%3F%3E%3Csvg%20xml%3Aspace%3D%22preserve%22%20viewBox%3D%220%200%20562%20562%22%20version%3D%221.1%22%20id%3D%22svg21%22%20%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%3E%20%20%3Cpath%20fill%3D%22%2300D8E9%22%20d%3D%22m330%20494-5%202-16%203c-20%206-42%204-63%204l-30-2c-5%200-10%200-14-2-6-4-14-4-20-7-3-2-7-1-9-3-5-6-12-4-17-10-4-4-12-6-18-9l-7-5-10-6-9-9-10-9-7-8c-5-6-11-11-14-18-4-7-10-12-15-18l-7-16-2-4c-4-4-4-10-6-15-2-6-6-12-5-19-6-3-3-10-5-15-3-4-2-10-2-16l-1-56%202-20c1-4%201-10%203-13%203-5%202-9%203-13%202-4%206-8%206-12%200-9%207-14%2010-22%203-10%2010-19%2016-27l12-15%208-8%2014-14%2011-8c3-4%209-4%2011-9l3-2%2017-8%2017-10%2015-5c5-3%2011-1%2015-5%202-2%205-2%207-2%2026-4%2052-3%2077-3%2011%200%2022%202%2033%204%205%201%209%204%2013%206l14%204%2020%209%2020%2011c4%202%206%206%209%208l3%202h3l14%2015%207%207%2010%209%208%2011%208%2012c3%204%208%208%208%2014l5%207%208%2018%206%2013%201%207c3%2011%206%2022%206%2034v19c0%2020%202%2041-4%2060l-6%2025c-3%2012-9%2023-15%2033-5%209-9%2020-18%2027-5%207-10%2015-17%2021l-19%2018-15%2011-26%2016-20%209-11%204m38-294c1-3-2-7%203-9%202-1%203-4%204-6%203-10%203-19-5-27-6-5-13-10-22-10-4%200-5%202-7%203-10%203-13%2011-17%2019H208c0-8%201-7-8-19-3-4-7-6-11-6-8%200-17-2-24%206-4%205-7%2011-9%2017-2%204%201%209%203%2013%201%202%202%205%204%206%205%204%206%208%204%2014-1%204-1%209-5%2013-2%203-1%209-2%2013%200%202%200%205-2%207-3%204-4%209-5%2014l-8%2024-2%204-9%204c-5%203-9%207-10%2013-2%209-3%2018%206%2026%205%205%2010%209%2017%209%206%200%2012%200%2016%205l5%202%2016%2012%2017%2010%2015%2012%2014%208%2014%2010%209%206c-1%2010%205%2017%2014%2023%207%204%2013%201%2020%201%202%200%205-1%207-3l5-6c3-2%203-5%204-8%201-2%204-13%202-14-5-2-2-6-3-8l5-4c6-4%2012-7%2015-12%204-5%2010-7%2014-12%207-8%2017-14%2025-21l3-1c7-1%2014%201%2021-4%205-4%209-7%2011-13l1-13c0-7-6-15-12-18-3-2-6-4-7-7l-2-21c-1-9-2-18-5-27-2-8-2-16-3-25z%22%20id%3D%22path11%22%20%2F%3E%20%20%3Cpath%20fill%3D%22%23041858%22%20d%3D%22m330%20494%2011-4%2020-9%2026-16%2015-11%2019-18c7-6%2012-14%2017-21%209-7%2013-18%2018-27%206-10%2012-21%2015-33l6-25c6-19%204-40%204-60v-19l10%2014%2012%2015%2012%2015%207%208c2%202%205%204%205%208l5%203c4%209%203%2015-7%2022l-19%2013c-4%203-7%207-9%2011-1%203%200%207%201%2010%201%204%206%207%205%2010%200%206-4%2010-9%2013l-8%206%202%201c4%200%209%200%209%205%201%205%201%2010-3%2014-6%206-12%2012-10%2021l5%2017%201%207c1%209-2%2017-9%2023l-7%202c-12%205-24%204-36%202-22-3-44-2-66-1l-40%205-2-1z%22%20id%3D%22path13%22%20%2F%3E%20%20%3Cpath%20d%3D%22m368%20201%203%2024c3%209%204%2018%205%2027l2%2021c1%203%204%205%207%207%206%203%2012%2011%2012%2018l-1%2013c-2%206-6%209-11%2013-7%205-14%203-21%204l-3%201c-8%207-18%2013-25%2021-4%205-10%207-14%2012-3%205-9%208-15%2012l-5%204c1%202-2%206%203%208%202%201-1%2012-2%2014-1%203-1%206-4%208l-5%206c-2%202-5%203-7%203-7%200-13%203-20-1-9-6-15-13-14-23l-9-6-14-10-14-8-15-12-17-10-16-12-5-2c-4-5-10-5-16-5-7%200-12-4-17-9-9-8-8-17-6-26%201-6%205-10%2010-13l9-4%202-4%208-24c1-5%202-10%205-14%202-2%202-5%202-7%201-4%200-10%202-13%204-4%204-9%205-13%202-6%201-10-4-14-2-1-3-4-4-6-2-4-5-9-3-13%202-6%205-12%209-17%207-8%2016-6%2024-6%204%200%208%202%2011%206%209%2012%208%2011%208%2019h116c4-8%207-16%2017-19%202-1%203-3%207-3%209%200%2016%205%2022%2010%208%208%208%2017%205%2027-1%202-2%205-4%206-5%202-2%206-3%2010m-46-21H208c-3%203-4%208-9%209l1%204%205%208c2%205%202%209%206%2013%203%204%203%2010%205%2015l5%208%206%2015c3%206%207%207%2012%208l14%204c1-3%202-5%204-6l12-8c6-6%2011-12%2019-15l1-1%206-7%2014-13%2015-10%207-7-1-5-3-5-5-7m-60%20104%2033%203c12%202%2024%200%2036%203h18c4-11%2014-11%2022-16v-7l-4-14-1-15-1-10-4-14-1-13h-13c-4%200-7-1-10%203l-9%208c-2%203-4%205-9%205%201%207-6%207-9%2010l-10%208-10%209-17%2014-13%2010-1%204%203%2012m-2%2011c-1%204%200%209-8%2011l4%209%202%206%204%2010%203%204%206%2018%203%205%205%208c3%200%208%200%2012%205%201%201%205%201%207-1%205-3%208-9%2015-11v-1l10-10c7-5%2015-10%2020-17l3-2c4-2%207-6%207-9-6-6-7-13-10-18l-28-4c-11-2-23%203-35-3-5-2-13-1-20%200m-19%2019c-7%201-13-1-18-5-4-2-6-6-9-8-1-2-3-3-5-3l-16%202-16%201-6%2015-2%202c-4%201-2%204-2%205l6%206c11%204%2020%2011%2029%2018l8%205%2015%2010%2015%2010%207%206c3%201%207%201%209-2l7-5c6-1%204-5%203-8%200-3-2-6-4-10l-7-14-1-5c-2-4-5-8-6-13-1-3-4-5-7-7m-73-84c-3%2010-4%2021-9%2030l-1%203-4%2013c6%204%2014%205%2016%2013l3%202h7l15-3%2013-1c3-6%204-12%208-15%202-3%204-6%204-9%200-4-2-8-5-12l-11-27c-1-3-1-6-3-8-4-3-4-7-5-11-1-3-4-6-7-8h-11l-1%206-4%2010-3%2015-2%202z%22%20id%3D%22path15%22%20%2F%3E%20%20%3Cpath%20fill%3D%22%2300D8E9%22%20d%3D%22m323%20180%204%207%203%205%201%205-7%207-15%2010-14%2013-6%207-1%201c-8%203-13%209-19%2015l-12%208c-2%201-3%203-4%206l-14-4c-5-1-9-2-12-8l-6-15-5-8c-2-5-2-11-5-15-4-4-4-8-6-13l-5-8-1-4c5-1%206-6%2010-9l4%201h106l4-1zM262%20284l-3-12%201-4%2013-10%2017-14%2010-9%2010-8c3-3%2010-3%209-10%205%200%207-2%209-5l9-8c3-4%206-3%2010-3h13l1%2013%204%2014%201%2010%201%2015%204%2014v7c-8%205-18%205-22%2016h-18c-12-3-24-1-36-3l-33-3zM261%20294c6%200%2014-1%2019%201%2012%206%2024%201%2035%203l28%204c3%205%204%2012%2010%2018%200%203-3%207-7%209l-3%202c-5%207-13%2012-20%2017l-10%2010v1c-7%202-10%208-15%2011-2%202-6%202-7%201-4-5-9-5-12-5l-5-8-3-5-6-18-3-4-4-10-2-6-4-9c8-2%207-7%209-12zM242%20314c2%202%205%204%206%207%201%205%204%209%206%2013l1%205%207%2014c2%204%204%207%204%2010%201%203%203%207-3%208l-7%205c-2%203-6%203-9%202l-7-6-15-10-15-10-8-5c-9-7-18-14-29-18l-6-6c0-1-2-4%202-5l2-2%206-15%2016-1%2016-2c2%200%204%201%205%203%203%202%205%206%209%208%205%204%2011%206%2019%205zM168%20230l2-2%203-15%204-10%201-6h11c3%202%206%205%207%208%201%204%201%208%205%2011%202%202%202%205%203%208l11%2027c3%204%205%208%205%2012%200%203-2%206-4%209-4%203-5%209-8%2015l-13%201-15%203h-7l-3-2c-2-8-10-9-16-13l4-13%201-3c5-9%206-20%209-30z%22%20id%3D%22path17%22%20%2F%3E%20%20%3Cpath%20fill%3D%22%2300D5D5%22%20d%3D%22m323%20180-4%201H213l-4-1h114z%22%20id%3D%22path19%22%20%2F%3E%3C%2Fsvg%3E";
 
+/// Icon used by `new` when the metadata doesn't provide one.
+const DATA_IMAGE_SVG_DEFAULT_ICON: &str = "data:image/svg+xml,%3Csvg%20version%3D%221.1%22%20id%3D%22layer%22%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%20xmlns%3Axlink%3D%22http%3A%2F%2Fwww.w3.org%2F1999%2Fxlink%22%20x%3D%220px%22%20y%3D%220px%22%20viewBox%3D%220%200%20652%20652%22%20style%3D%22enable-background%3Anew%200%200%20652%20652%3B%22%20xml%3Aspace%3D%22preserve%22%3E%3Cstyle%20type%3D%22text%2Fcss%22%3E.st0%7Bfill%3A%230064A6%3B%7D%3C%2Fstyle%3E%3Cg%3E%3Cpath%20class%3D%22st0%22%20d%3D%22M552.5%2C375.1c-5.6-6.4-13.7-10-22.1-10.1C518%2C365.1%2C507%2C373%2C503%2C384.7c0%2C0-1%2C3.6-1%2C3.6l-1.2%2C5.3c0%2C0-19.9%2C88.6-23.1%2C102.6c-0.8-3.7-19.9-92-19.9-92c-0.8-3.8-2-8.7-3.5-13.2l-1.3-3.8c-5.6-13.4-18.5-22-33-22c-13.8%2C0-26.5%2C8.1-32.4%2C20.7c-1%2C2.6-1.9%2C5.6-2.8%2C9c-0.8%2C3.2-1.5%2C6.5-2.1%2C9.3c0%2C0-16.9%2C79.3-19.7%2C92.4c-0.8-3.6-24.8-103-24.8-103l-1-4.2c-0.7-2.6-1.3-4.7-1.9-6.5c-4.6-10.7-15.1-17.6-26.7-17.7c-8.4%2C0-16.5%2C3.7-22.1%2C10.1c-4.1%2C4.7-6.5%2C10.5-7%2C16.8l-0.1%2C1.2c0%2C1.7%2C0.1%2C3.4%2C0.3%2C5.2c0.6%2C6%2C2.1%2C10.8%2C3.5%2C15.5l38.8%2C126.4l0.8%2C2.7l2%2C5.8c6%2C13.7%2C19.5%2C22.6%2C34.4%2C22.7h0.3c15.5%2C0%2C29.2-9.4%2C34.9-23.8c0-0.1%2C1.8-6.4%2C1.8-6.4c0%2C0%2C0.3-1.2%2C0.3-1.2s22.1-88%2C22.9-91.1c0.8%2C3.2%2C23.2%2C92.3%2C23.2%2C92.3l1.7%2C5.9c5.5%2C14.5%2C19.6%2C24.3%2C35.1%2C24.3h0.3c14.6-0.1%2C28-8.7%2C34.1-22c0.8-2.1%2C1.6-4.4%2C2.4-7l0.6-2.1l38.9-126.4c1.4-4.7%2C3-9.5%2C3.5-15.5c0.2-1.8%2C0.2-3.4%2C0.2-5.2l0-1.2C559.1%2C385.7%2C556.6%2C379.9%2C552.5%2C375.1%20M488.8%2C142.9c-22.4%2C0-41.7%2C7.8-56%2C22.4v-63.6v-0.5c-0.3-16.5-14-29.9-30.4-29.9c-16.8%2C0-30.4%2C13.7-30.4%2C30.4v217.9c0%2C16.8%2C13.6%2C30.4%2C30.4%2C30.4c16.6%2C0%2C30.2-13.5%2C30.4-30v-0.4v-87.5c0-23.4%2C18.9-37.9%2C36.4-37.9c25.7%2C0%2C29.5%2C20.5%2C29.5%2C32.8v92.6l0%2C0.5c0.2%2C16.5%2C13.9%2C29.9%2C30.4%2C29.9c16.8%2C0%2C30.4-13.7%2C30.4-30.4V216.6C559.6%2C170.5%2C533.1%2C142.9%2C488.8%2C142.9%20M269.6%2C512v-89c0-37.1-31.7-57.5-89.1-57.5c-53.9%2C0-87.2%2C25.6-87.2%2C50.1c0%2C13.6%2C11%2C24.6%2C24.6%2C24.6c5.9%2C0%2C11.3-2.1%2C15.6-5.6c2.7-2%2C5.2-4.4%2C7.7-6.9c8.2-8%2C17.5-17%2C38.9-17c14.7%2C0%2C30.4%2C4.9%2C30.4%2C18.8c0%2C12.1-6.8%2C15.1-20.2%2C16.6l-32.3%2C3.6c-38%2C4.4-76.8%2C15.5-76.8%2C64c0%2C35.8%2C32%2C57.1%2C62.9%2C57.1c29.1%2C0%2C51.6-8.8%2C70.4-27.3c2.9%2C17.6%2C14.6%2C27.3%2C33.5%2C27.3c15.1%2C0%2C27-10.3%2C27-23.4c0-3.6-0.9-6.6-1.9-10.1C271.6%2C532.1%2C269.6%2C525.3%2C269.6%2C512%20M213.5%2C494.6c0%2C19.6-17.8%2C38.5-46.8%2C38.5c-18.2%2C0-29.4-9.8-29.4-21.8c0-16%2C12-22.9%2C34.1-26.2l19.3-2.9c6.2-1.1%2C17.1-2.9%2C22.9-8.4V494.6z%20M180.4%2C176.9c0%2C10.7%2C6.8%2C20.1%2C17%2C23.5c2.7%2C0.8%2C5.5%2C1.1%2C8.3%2C1.1h67.8c-2.6%2C2.8-93.9%2C102.1-93.9%2C102.1c-5.3%2C5.6-7.9%2C13-7.9%2C21.9l0%2C1.2c0.5%2C10.8%2C8.8%2C22.7%2C25.8%2C23.4l1.7%2C0.1h135.1c2.9%2C0%2C5.7-0.4%2C8.4-1.2c10.1-3.4%2C16.9-12.7%2C16.9-23.4c0-10.6-6.8-20.1-16.9-23.4v0c-2.7-0.8-5.5-1.2-8.4-1.2H256c2.6-2.8%2C92.1-101%2C92.1-101c6-6.4%2C9.4-13.8%2C9.4-20.4l-0.1-2.5c-0.3-4.5-1.3-8.5-3.1-11.9c-2.8-5.5-7.4-9.4-13.3-11.3c-2.8-0.9-6-1.4-9.2-1.4h-126c-2.9%2C0-5.6%2C0.4-8.3%2C1.1C187.2%2C156.9%2C180.4%2C166.3%2C180.4%2C176.9%22%20%2F%3E%3C%2Fg%3E%3C%2Fsvg%3E";
+
 #[near_bindgen]
 impl Contract {
     /// Initializes the contract with the given total supply owned by the given `owner_id` with
@@ -51,26 +236,35 @@ impl Contract {
                 reference_hash: None,
                 decimals: 24,
             },
+            None,
         )
     }
 
     /// Initializes the contract with the given total supply owned by the given `owner_id` with
-    /// the given fungible token metadata.
+    /// the given fungible token metadata, using a default icon if it has none. If `max_supply`
+    /// is set, the total supply can never exceed it.
     #[init]
     pub fn new(
         owner_id: AccountId,
         total_supply: U128,
         metadata: FungibleTokenMetadata,
+        max_supply: Option<U128>,
     ) -> Self {
-        let icon = Some("data:image/svg+xml,%3Csvg%20version%3D%221.1%22%20id%3D%22layer%22%20xmlns%3D%22http%3A%2F%2Fwww.w3.org%2F2000%2Fsvg%22%20xmlns%3Axlink%3D%22http%3A%2F%2Fwww.w3.org%2F1999%2Fxlink%22%20x%3D%220px%22%20y%3D%220px%22%20viewBox%3D%220%200%20652%20652%22%20style%3D%22enable-background%3Anew%200%200%20652%20652%3B%22%20xml%3Aspace%3D%22preserve%22%3E%3Cstyle%20type%3D%22text%2Fcss%22%3E.st0%7Bfill%3A%230064A6%3B%7D%3C%2Fstyle%3E%3Cg%3E%3Cpath%20class%3D%22st0%22%20d%3D%22M552.5%2C375.1c-5.6-6.4-13.7-10-22.1-10.1C518%2C365.1%2C507%2C373%2C503%2C384.7c0%2C0-1%2C3.6-1%2C3.6l-1.2%2C5.3c0%2C0-19.9%2C88.6-23.1%2C102.6c-0.8-3.7-19.9-92-19.9-92c-0.8-3.8-2-8.7-3.5-13.2l-1.3-3.8c-5.6-13.4-18.5-22-33-22c-13.8%2C0-26.5%2C8.1-32.4%2C20.7c-1%2C2.6-1.9%2C5.6-2.8%2C9c-0.8%2C3.2-1.5%2C6.5-2.1%2C9.3c0%2C0-16.9%2C79.3-19.7%2C92.4c-0.8-3.6-24.8-103-24.8-103l-1-4.2c-0.7-2.6-1.3-4.7-1.9-6.5c-4.6-10.7-15.1-17.6-26.7-17.7c-8.4%2C0-16.5%2C3.7-22.1%2C10.1c-4.1%2C4.7-6.5%2C10.5-7%2C16.8l-0.1%2C1.2c0%2C1.7%2C0.1%2C3.4%2C0.3%2C5.2c0.6%2C6%2C2.1%2C10.8%2C3.5%2C15.5l38.8%2C126.4l0.8%2C2.7l2%2C5.8c6%2C13.7%2C19.5%2C22.6%2C34.4%2C22.7h0.3c15.5%2C0%2C29.2-9.4%2C34.9-23.8c0-0.1%2C1.8-6.4%2C1.8-6.4c0%2C0%2C0.3-1.2%2C0.3-1.2s22.1-88%2C22.9-91.1c0.8%2C3.2%2C23.2%2C92.3%2C23.2%2C92.3l1.7%2C5.9c5.5%2C14.5%2C19.6%2C24.3%2C35.1%2C24.3h0.3c14.6-0.1%2C28-8.7%2C34.1-22c0.8-2.1%2C1.6-4.4%2C2.4-7l0.6-2.1l38.9-126.4c1.4-4.7%2C3-9.5%2C3.5-15.5c0.2-1.8%2C0.2-3.4%2C0.2-5.2l0-1.2C559.1%2C385.7%2C556.6%2C379.9%2C552.5%2C375.1%20M488.8%2C142.9c-22.4%2C0-41.7%2C7.8-56%2C22.4v-63.6v-0.5c-0.3-16.5-14-29.9-30.4-29.9c-16.8%2C0-30.4%2C13.7-30.4%2C30.4v217.9c0%2C16.8%2C13.6%2C30.4%2C30.4%2C30.4c16.6%2C0%2C30.2-13.5%2C30.4-30v-0.4v-87.5c0-23.4%2C18.9-37.9%2C36.4-37.9c25.7%2C0%2C29.5%2C20.5%2C29.5%2C32.8v92.6l0%2C0.5c0.2%2C16.5%2C13.9%2C29.9%2C30.4%2C29.9c16.8%2C0%2C30.4-13.7%2C30.4-30.4V216.6C559.6%2C170.5%2C533.1%2C142.9%2C488.8%2C142.9%20M269.6%2C512v-89c0-37.1-31.7-57.5-89.1-57.5c-53.9%2C0-87.2%2C25.6-87.2%2C50.1c0%2C13.6%2C11%2C24.6%2C24.6%2C24.6c5.9%2C0%2C11.3-2.1%2C15.6-5.6c2.7-2%2C5.2-4.4%2C7.7-6.9c8.2-8%2C17.5-17%2C38.9-17c14.7%2C0%2C30.4%2C4.9%2C30.4%2C18.8c0%2C12.1-6.8%2C15.1-20.2%2C16.6l-32.3%2C3.6c-38%2C4.4-76.8%2C15.5-76.8%2C64c0%2C35.8%2C32%2C57.1%2C62.9%2C57.1c29.1%2C0%2C51.6-8.8%2C70.4-27.3c2.9%2C17.6%2C14.6%2C27.3%2C33.5%2C27.3c15.1%2C0%2C27-10.3%2C27-23.4c0-3.6-0.9-6.6-1.9-10.1C271.6%2C532.1%2C269.6%2C525.3%2C269.6%2C512%20M213.5%2C494.6c0%2C19.6-17.8%2C38.5-46.8%2C38.5c-18.2%2C0-29.4-9.8-29.4-21.8c0-16%2C12-22.9%2C34.1-26.2l19.3-2.9c6.2-1.1%2C17.1-2.9%2C22.9-8.4V494.6z%20M180.4%2C176.9c0%2C10.7%2C6.8%2C20.1%2C17%2C23.5c2.7%2C0.8%2C5.5%2C1.1%2C8.3%2C1.1h67.8c-2.6%2C2.8-93.9%2C102.1-93.9%2C102.1c-5.3%2C5.6-7.9%2C13-7.9%2C21.9l0%2C1.2c0.5%2C10.8%2C8.8%2C22.7%2C25.8%2C23.4l1.7%2C0.1h135.1c2.9%2C0%2C5.7-0.4%2C8.4-1.2c10.1-3.4%2C16.9-12.7%2C16.9-23.4c0-10.6-6.8-20.1-16.9-23.4v0c-2.7-0.8-5.5-1.2-8.4-1.2H256c2.6-2.8%2C92.1-101%2C92.1-101c6-6.4%2C9.4-13.8%2C9.4-20.4l-0.1-2.5c-0.3-4.5-1.3-8.5-3.1-11.9c-2.8-5.5-7.4-9.4-13.3-11.3c-2.8-0.9-6-1.4-9.2-1.4h-126c-2.9%2C0-5.6%2C0.4-8.3%2C1.1C187.2%2C156.9%2C180.4%2C166.3%2C180.4%2C176.9%22%20%2F%3E%3C%2Fg%3E%3C%2Fsvg%3E".to_string());
-        let metadata = FungibleTokenMetadata { spec: metadata.spec, name: metadata.name, symbol: metadata.symbol, icon, reference:None, reference_hash: None, decimals: metadata.decimals };
+        let metadata = FungibleTokenMetadata {
+            icon: metadata.icon.or_else(|| Some(DATA_IMAGE_SVG_DEFAULT_ICON.to_string())),
+            ..metadata
+        };
         assert!(!env::state_exists(), "Already initialized");
         metadata.assert_valid();
-        let mut this = Self {
-            token: FungibleToken::new(b"a".to_vec()),
-            metadata: LazyOption::new(b"m".to_vec(), Some(&metadata)),
-        };
-        this.token.internal_register_account(&owner_id);
+        if let Some(max_supply) = max_supply {
+            require!(total_supply.0 <= max_supply.0, "The total supply exceeds the maximum supply");
+        }
+        let mut this = Self::internal_new(
+            FungibleToken::new(TOKEN_PREFIX),
+            LazyOption::new(METADATA_PREFIX, Some(&metadata)),
+            owner_id.clone(),
+            max_supply.map(|max_supply| max_supply.0),
+        );
         this.token.internal_deposit(&owner_id, total_supply.into());
         near_contract_standards::fungible_token::events::FtMint {
             owner_id: &owner_id,
@@ -90,8 +284,422 @@ impl Contract {
     }
 }
 
-near_contract_standards::impl_fungible_token_core!(Contract, token, on_tokens_burned);
-near_contract_standards::impl_fungible_token_storage!(Contract, token, on_account_closed);
+impl Contract {
+    /// Builds the state around the given token and metadata, with every other feature empty, and
+    /// registers the owner and the contract's own account unless they are registered already.
+    fn internal_new(
+        token: FungibleToken,
+        metadata: LazyOption<FungibleTokenMetadata>,
+        owner_id: AccountId,
+        max_supply: Option<Balance>,
+    ) -> Self {
+        let mut this = Self {
+            token,
+            metadata,
+            owner_id: owner_id.clone(),
+            pending_owner_id: None,
+            max_supply,
+            storage_sponsors: LookupMap::new(b"ss".to_vec()),
+            sponsored_accounts: LookupMap::new(b"sa".to_vec()),
+            transfer_policies: LookupMap::new(b"tp".to_vec()),
+            queued_transfers: LookupMap::new(b"tq".to_vec()),
+            next_queued_transfer_id: 0,
+            transfer_confirmations: LookupMap::new(b"tc".to_vec()),
+            pending_transfers: LookupMap::new(b"pt".to_vec()),
+            next_pending_transfer_id: 0,
+            operators: LookupMap::new(b"op".to_vec()),
+            migration_consents: LookupMap::new(b"mc".to_vec()),
+            deposit_notifiers: LookupMap::new(b"dn".to_vec()),
+            idempotency_keys: LookupMap::new(b"ik".to_vec()),
+            approval_freezes: LookupMap::new(b"af".to_vec()),
+            display_handles: LookupMap::new(b"dh".to_vec()),
+            refund_claims: LookupMap::new(b"rc".to_vec()),
+            refund_recovery_id: None,
+            pending_credits: LookupMap::new(b"pc".to_vec()),
+            next_pending_credit_id: 0,
+            account_activity: LookupMap::new(b"la".to_vec()),
+            inbound_allowlists: LookupMap::new(b"ia".to_vec()),
+            roles: LookupMap::new(b"ro".to_vec()),
+            paused: PausedFeatures::default(),
+            blacklist: LookupSet::new(b"bl".to_vec()),
+            transfer_mode: TransferMode::Open,
+            whitelist: LookupSet::new(b"wl".to_vec()),
+            frozen_accounts: LookupMap::new(b"fz".to_vec()),
+            seizures: Vector::new(b"sz".to_vec()),
+            allowances: LookupMap::new(b"al".to_vec()),
+            allowance_spenders: LookupMap::new(b"as".to_vec()),
+            allowance_owners: LookupMap::new(b"ao".to_vec()),
+            permit_keys: LookupMap::new(b"pk".to_vec()),
+            in_flight: LookupMap::new(b"if".to_vec()),
+            vestings: LookupMap::new(b"vs".to_vec()),
+            merchants: LookupMap::new(b"me".to_vec()),
+            merchant_payments: LookupMap::new(b"mp".to_vec()),
+            next_merchant_payment_id: 0,
+            next_vesting_grant_id: 0,
+            vesting_treasury_id: None,
+            lockups: LookupMap::new(b"lk".to_vec()),
+            streams: LookupMap::new(b"st".to_vec()),
+            streams_by_sender: LookupMap::new(b"sn".to_vec()),
+            streams_by_receiver: LookupMap::new(b"sr".to_vec()),
+            next_stream_id: 0,
+            custodies: LookupMap::new(b"cu".to_vec()),
+            distributions: LookupMap::new(b"di".to_vec()),
+            next_distribution_id: 0,
+            claimed_leaves: LookupMap::new(b"cl".to_vec()),
+            savings_goals: LookupMap::new(b"sg".to_vec()),
+            roscas: LookupMap::new(b"rs".to_vec()),
+            next_rosca_id: 0,
+            fee_bps: 0,
+            fee_collector: None,
+        };
+        this.measure_activity_storage_usage();
+        this.roles.insert(&owner_id, &RoleSet::all());
+        // The contract's own account holds tokens of transfers that are not settled yet.
+        for account_id in [owner_id, env::current_account_id()] {
+            if !this.token.accounts.contains_key(&account_id) {
+                this.token.internal_register_account(&account_id);
+            }
+        }
+        state_migration::write_state_version();
+        this
+    }
+
+    /// Panics unless `account_id` can close its registration, by unregistering or by sweeping its
+    /// balance to another account.
+    pub(crate) fn internal_assert_can_close(&self, account_id: &AccountId) {
+        self.assert_not_paused(Feature::Storage);
+        require!(*account_id != env::current_account_id(), "The contract account can't be closed");
+        require!(!self.frozen_accounts.contains_key(account_id), "The account is frozen");
+        require!(!self.vestings.contains_key(account_id), "The account has vesting schedules");
+        require!(!self.savings_goals.contains_key(account_id), "The account has savings goals");
+        require!(
+            self.fee_collector.as_ref() != Some(account_id),
+            "The fee collector can't unregister"
+        );
+        require!(
+            !self.streams_by_sender.contains_key(account_id)
+                && !self.streams_by_receiver.contains_key(account_id),
+            "The account has open streams"
+        );
+        require!(!self.merchants.contains_key(account_id), "Unregister the merchant first");
+        if let Some(confirmation) = self.transfer_confirmations.get(account_id) {
+            require!(confirmation.pending == 0, "Settle the pending transfers first");
+        }
+    }
+
+    /// Drops the records of the closed `account_id` and refunds their storage, together with the
+    /// registration deposit and the attached yoctoNEAR, to `beneficiary_id`. A registration paid
+    /// by a storage sponsor goes back to the sponsor's balance instead.
+    pub(crate) fn internal_close_account(
+        &mut self,
+        account_id: &AccountId,
+        beneficiary_id: AccountId,
+    ) {
+        let initial_storage_usage = env::storage_usage();
+        self.transfer_confirmations.remove(account_id);
+        self.operators.remove(account_id);
+        self.migration_consents.remove(account_id);
+        self.deposit_notifiers.remove(account_id);
+        self.idempotency_keys.remove(account_id);
+        self.approval_freezes.remove(account_id);
+        self.display_handles.remove(account_id);
+        self.inbound_allowlists.remove(account_id);
+        self.internal_remove_allowances(account_id);
+        self.permit_keys.remove(account_id);
+        self.in_flight.remove(account_id);
+        self.lockups.remove(account_id);
+        self.custodies.remove(account_id);
+        let released = initial_storage_usage - env::storage_usage();
+        // Paid for by the registration deposit.
+        self.account_activity.remove(account_id);
+
+        let mut refund = 1 + Balance::from(released) * env::storage_byte_cost();
+        if !self.internal_refund_sponsor(account_id) {
+            refund += Balance::from(self.token.storage_balance_bounds().min);
+        }
+        Promise::new(beneficiary_id).transfer(refund);
+    }
+
+    /// Moves tokens from `sender_id` to `receiver_id`, less the transfer fee, and notifies the
+    /// receiver's deposit notifier, unless the receiver only accepts confirmed transfers, in which
+    /// case the tokens are held until it accepts them. Returns the storage taken by the pending
+    /// transfer, if any, for the caller to charge to the attached deposit.
+    pub(crate) fn internal_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> StorageUsage {
+        match self.internal_start_transfer(sender_id, receiver_id, amount, &memo) {
+            Ok(amount) => {
+                self.token.internal_transfer(sender_id, receiver_id, amount, memo.clone());
+                self.internal_finish_transfer(sender_id, receiver_id, amount, memo);
+                0
+            }
+            Err(pending_storage) => pending_storage,
+        }
+    }
+
+    /// Checks a transfer and charges its fee. Returns the amount to credit to the receiver, or,
+    /// if the receiver only accepts confirmed transfers and the tokens are now held until it
+    /// accepts them, `Err` with the storage taken by the pending transfer.
+    pub(crate) fn internal_start_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &Option<String>,
+    ) -> Result<Balance, StorageUsage> {
+        self.internal_check_transfer(sender_id, receiver_id, amount);
+        let amount = self.internal_charge_fee(sender_id, receiver_id, amount);
+        self.internal_hold_for_confirmation(sender_id, receiver_id, amount, memo)
+    }
+
+    /// Panics if the transfer isn't allowed or the receiver doesn't accept it, and drops the
+    /// sender's lockup once it has ended.
+    pub(crate) fn internal_check_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.internal_assert_transfer_allowed(sender_id, receiver_id, amount);
+        self.internal_assert_accepts_from(receiver_id, sender_id);
+        self.internal_clear_ended_lockup(sender_id);
+    }
+
+    /// Holds the transfer as pending if the receiver only accepts confirmed transfers, returning
+    /// the storage it takes, or returns the amount to credit to the receiver right away.
+    pub(crate) fn internal_hold_for_confirmation(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: &Option<String>,
+    ) -> Result<Balance, StorageUsage> {
+        if self.transfer_confirmations.contains_key(receiver_id) {
+            Err(self.internal_create_pending_transfer(sender_id, receiver_id, amount, memo.clone()))
+        } else {
+            Ok(amount)
+        }
+    }
+
+    /// Records a transfer credited to the receiver and notifies the receiver's deposit notifier.
+    pub(crate) fn internal_finish_transfer(
+        &mut self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        self.internal_record_activity(sender_id, receiver_id);
+        self.internal_emit_transfer_handles(sender_id, receiver_id, amount);
+        self.internal_notify_deposit(sender_id, receiver_id, amount, memo);
+    }
+
+    /// Queues the transfer if it exceeds the sender's soft limit, or makes it right away. Returns
+    /// the storage the caller charges to the attached deposit.
+    pub(crate) fn internal_ft_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> StorageUsage {
+        if self.internal_exceeds_soft_limit(&sender_id, amount) {
+            self.internal_queue_transfer(sender_id, receiver_id, amount, memo)
+        } else {
+            self.internal_transfer(&sender_id, &receiver_id, amount, memo)
+        }
+    }
+
+    /// Panics if `amount` can't move from `sender_id` to `receiver_id` right now.
+    pub(crate) fn internal_assert_transfer_allowed(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+    ) {
+        self.assert_not_paused(Feature::Transfer);
+        self.internal_assert_not_blacklisted(sender_id, receiver_id);
+        self.internal_assert_unfrozen(sender_id, amount);
+        self.internal_assert_unlocked(sender_id, amount);
+        self.internal_assert_custody_allows(sender_id, amount);
+        self.internal_assert_whitelisted(receiver_id);
+    }
+
+    /// Moves `amount` from `account_id` into the contract's own balance.
+    pub(crate) fn internal_hold(&mut self, account_id: &AccountId, amount: Balance, memo: &str) {
+        let contract_id = env::current_account_id();
+        self.internal_assert_transfer_allowed(account_id, &contract_id, amount);
+        self.token.internal_transfer(account_id, &contract_id, amount, Some(memo.to_string()));
+        self.internal_record_activity(account_id, &contract_id);
+    }
+
+    /// Moves `amount` held by the contract to `account_id`.
+    pub(crate) fn internal_release(&mut self, account_id: &AccountId, amount: Balance, memo: &str) {
+        let contract_id = env::current_account_id();
+        self.internal_assert_transfer_allowed(&contract_id, account_id, amount);
+        self.token.internal_transfer(&contract_id, account_id, amount, Some(memo.to_string()));
+        self.internal_record_activity(&contract_id, account_id);
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenCore for Contract {
+    /// Transfers above the sender's soft limit are queued for its approver instead of moving
+    /// the tokens right away. A transfer held for the receiver's confirmation is paid for out of
+    /// the attached deposit, and the rest of the deposit is refunded.
+    #[payable]
+    fn ft_transfer(&mut self, receiver_id: AccountId, amount: U128, memo: Option<String>) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        self.assert_not_paused(Feature::Transfer);
+        let sender_id = env::predecessor_account_id();
+        refund_deposit(self.internal_ft_transfer(sender_id, receiver_id, amount.into(), memo));
+    }
+
+    #[payable]
+    fn ft_transfer_call(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        msg: String,
+    ) -> PromiseOrValue<U128> {
+        self.assert_not_paused(Feature::TransferCall);
+        let sender_id = env::predecessor_account_id();
+        self.internal_assert_not_blacklisted(&sender_id, &receiver_id);
+        self.internal_assert_whitelisted(&receiver_id);
+        self.internal_assert_unfrozen(&sender_id, amount.into());
+        self.internal_assert_unlocked(&sender_id, amount.into());
+        self.internal_assert_custody_allows(&sender_id, amount.into());
+        require!(
+            !self.internal_exceeds_soft_limit(&sender_id, amount.into()),
+            "The amount exceeds the soft limit, use ft_transfer to queue it for approval"
+        );
+        require!(
+            !self.transfer_confirmations.contains_key(&receiver_id),
+            "The receiver only accepts confirmed transfers, use ft_transfer"
+        );
+        self.internal_assert_accepts_from(&receiver_id, &sender_id);
+        self.internal_clear_ended_lockup(&sender_id);
+        let received = self.internal_charge_fee(&sender_id, &receiver_id, amount.into());
+        let fee = amount.0 - received;
+        if self.in_flight.contains_key(&sender_id) {
+            assert_one_yocto();
+            return self.internal_in_flight_transfer_call(
+                sender_id,
+                receiver_id,
+                received,
+                fee,
+                memo,
+                msg,
+            );
+        }
+        self.internal_record_activity(&sender_id, &receiver_id);
+        if fee > 0 {
+            return self.internal_fee_transfer_call(
+                sender_id,
+                receiver_id,
+                received,
+                fee,
+                memo,
+                msg,
+            );
+        }
+        self.token.ft_transfer_call(receiver_id, received.into(), memo, msg)
+    }
+
+    fn ft_total_supply(&self) -> U128 {
+        self.token.ft_total_supply()
+    }
+
+    fn ft_balance_of(&self, account_id: AccountId) -> U128 {
+        self.token.ft_balance_of(account_id)
+    }
+}
+
+#[near_bindgen]
+impl FungibleTokenResolver for Contract {
+    #[private]
+    fn ft_resolve_transfer(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+    ) -> U128 {
+        // A sender that unregistered while the call was in flight gets its refund parked in the
+        // contract's balance until it registers again, instead of having it burned. So does a
+        // sender that was blacklisted in the meantime, until it is removed from the list.
+        if self.token.accounts.contains_key(&sender_id) && !self.blacklist.contains(&sender_id) {
+            let (used_amount, burned_amount) =
+                self.token.internal_ft_resolve_transfer(&sender_id, receiver_id, amount);
+            if burned_amount > 0 {
+                self.on_tokens_burned(sender_id, burned_amount);
+            }
+            used_amount.into()
+        } else {
+            let (used_amount, _) = self.token.internal_ft_resolve_transfer(
+                &env::current_account_id(),
+                receiver_id,
+                amount,
+            );
+            self.internal_park_refund(sender_id, amount.0 - used_amount);
+            used_amount.into()
+        }
+    }
+}
+
+#[near_bindgen]
+impl StorageManagement for Contract {
+    #[payable]
+    fn storage_deposit(
+        &mut self,
+        account_id: Option<AccountId>,
+        registration_only: Option<bool>,
+    ) -> StorageBalance {
+        self.assert_not_paused(Feature::Storage);
+        self.token.storage_deposit(account_id, registration_only)
+    }
+
+    #[payable]
+    fn storage_withdraw(&mut self, amount: Option<U128>) -> StorageBalance {
+        self.assert_not_paused(Feature::Storage);
+        self.token.storage_withdraw(amount)
+    }
+
+    #[payable]
+    fn storage_unregister(&mut self, force: Option<bool>) -> bool {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.internal_assert_can_close(&account_id);
+        let balance = match self.token.accounts.get(&account_id) {
+            Some(balance) => balance,
+            None => {
+                log!("The account {} is not registered", &account_id);
+                return false;
+            }
+        };
+        require!(
+            balance == 0 || force.unwrap_or(false),
+            "Can't unregister the account with the positive balance without force"
+        );
+        self.token.accounts.remove(&account_id);
+        self.token.total_supply -= balance;
+        self.internal_close_account(&account_id, account_id.clone());
+        self.on_account_closed(account_id, balance);
+        true
+    }
+
+    fn storage_balance_bounds(&self) -> StorageBalanceBounds {
+        self.token.storage_balance_bounds()
+    }
+
+    fn storage_balance_of(&self, account_id: AccountId) -> Option<StorageBalance> {
+        self.token.storage_balance_of(account_id)
+    }
+}
 
 #[near_bindgen]
 impl FungibleTokenMetadataProvider for Contract {
@@ -102,15 +710,15 @@ impl FungibleTokenMetadataProvider for Contract {
 
 #[cfg(all(test, not(target_arch = "wasm32")))]
 mod tests {
-    use near_sdk::test_utils::{accounts, VMContextBuilder};
-    use near_sdk::MockedBlockchain;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
     use near_sdk::{testing_env, Balance};
 
     use super::*;
 
-    const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
+    pub(crate) const TOTAL_SUPPLY: Balance = 1_000_000_000_000_000;
 
-    fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
+    pub(crate) fn get_context(predecessor_account_id: AccountId) -> VMContextBuilder {
         let mut builder = VMContextBuilder::new();
         builder
             .current_account_id(accounts(0))
@@ -119,16 +727,81 @@ mod tests {
         builder
     }
 
+    /// Registers `account_id` with the minimum storage deposit, paid by the current predecessor,
+    /// and leaves the context attaching 1 yoctoNEAR.
+    pub(crate) fn register(
+        context: &mut VMContextBuilder,
+        contract: &mut Contract,
+        account_id: AccountId,
+    ) {
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(contract.storage_balance_bounds().min.into())
+            .build());
+        contract.storage_deposit(Some(account_id), None);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+    }
+
+    /// Creates the contract owned by accounts(2), which holds the total supply and is left as the
+    /// caller, and registers `account_ids`.
+    pub(crate) fn setup_contract(account_ids: &[AccountId]) -> (VMContextBuilder, Contract) {
+        let mut context = get_context(accounts(2));
+        testing_env!(context.build());
+        let mut contract = Contract::new_default_meta(accounts(2), TOTAL_SUPPLY.into());
+        for account_id in account_ids {
+            register(&mut context, &mut contract, account_id.clone());
+        }
+        (context, contract)
+    }
+
+    /// Brings the context up to date with the storage usage and attaches enough to pay for
+    /// 1000 bytes of storage.
+    pub(crate) fn with_storage_deposit(context: &mut VMContextBuilder) -> &mut VMContextBuilder {
+        context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 1000)
+    }
+
+    /// Returns the amount of the last receipt, which must be a plain transfer to `account_id`.
+    pub(crate) fn last_refund_to(account_id: AccountId) -> Balance {
+        let receipt = get_created_receipts().pop().unwrap();
+        assert_eq!(receipt.receiver_id, account_id);
+        match receipt.actions[..] {
+            [VmAction::Transfer { deposit }] => deposit,
+            _ => panic!("Expected a transfer"),
+        }
+    }
+
     #[test]
     fn test_new() {
         let mut context = get_context(accounts(1));
         testing_env!(context.build());
-        let contract = Contract::new_default_meta(accounts(1).into(), TOTAL_SUPPLY.into());
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
         testing_env!(context.is_view(true).build());
         assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
         assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
     }
 
+    #[test]
+    fn test_new_keeps_provided_metadata() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Branded token".to_string(),
+            symbol: "BRD".to_string(),
+            icon: Some("data:image/svg+xml,%3Csvg%3E%3C%2Fsvg%3E".to_string()),
+            reference: Some("https://example.com/brd.json".to_string()),
+            reference_hash: Some(vec![7; 32].into()),
+            decimals: 18,
+        };
+        let contract = Contract::new(accounts(1), TOTAL_SUPPLY.into(), metadata.clone(), None);
+        let stored = contract.ft_metadata();
+        assert_eq!(stored.icon, metadata.icon);
+        assert_eq!(stored.reference, metadata.reference);
+        assert_eq!(stored.reference_hash, metadata.reference_hash);
+    }
+
     #[test]
     #[should_panic(expected = "The contract is not initialized")]
     fn test_default() {
@@ -141,7 +814,7 @@ mod tests {
     fn test_transfer() {
         let mut context = get_context(accounts(2));
         testing_env!(context.build());
-        let mut contract = Contract::new_default_meta(accounts(2).into(), TOTAL_SUPPLY.into());
+        let mut contract = Contract::new_default_meta(accounts(2), TOTAL_SUPPLY.into());
         testing_env!(context
             .storage_usage(env::storage_usage())
             .attached_deposit(contract.storage_balance_bounds().min.into())
@@ -167,4 +840,83 @@ mod tests {
         assert_eq!(contract.ft_balance_of(accounts(2)).0, (TOTAL_SUPPLY - transfer_amount));
         assert_eq!(contract.ft_balance_of(accounts(1)).0, transfer_amount);
     }
+
+    /// Returns an account ID of the maximum length, unique for each `i`.
+    fn long_account_id(i: usize) -> AccountId {
+        format!("{:0>64}", i).parse().unwrap()
+    }
+
+    #[test]
+    fn test_mass_registrations_are_paid() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let min_deposit: Balance = contract.storage_balance_bounds().min.into();
+        let initial_storage_usage = env::storage_usage();
+        for i in 0..50 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(min_deposit)
+                .predecessor_account_id(long_account_id(i))
+                .build());
+            contract.storage_deposit(None, None);
+        }
+        let storage_cost =
+            env::storage_byte_cost() * Balance::from(env::storage_usage() - initial_storage_usage);
+        assert!(storage_cost <= min_deposit * 50);
+    }
+
+    #[test]
+    fn test_register_unregister_cycles() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let min_deposit: Balance = contract.storage_balance_bounds().min.into();
+        let initial_storage_usage = env::storage_usage();
+        for _ in 0..20 {
+            testing_env!(context
+                .storage_usage(env::storage_usage())
+                .attached_deposit(min_deposit)
+                .predecessor_account_id(long_account_id(0))
+                .build());
+            contract.storage_deposit(None, None);
+            testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+            assert!(contract.storage_unregister(None));
+        }
+        assert_eq!(env::storage_usage(), initial_storage_usage);
+    }
+
+    #[test]
+    #[should_panic(expected = "The attached deposit is less than the minimum storage balance")]
+    fn test_registration_below_minimum_deposit() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let min_deposit: Balance = contract.storage_balance_bounds().min.into();
+        testing_env!(context
+            .attached_deposit(min_deposit - 1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.storage_deposit(None, None);
+    }
+
+    #[test]
+    fn test_memo_is_not_stored() {
+        let (_, mut contract) = setup_contract(&[accounts(1)]);
+        // The first transfer stores the activity records of both accounts.
+        contract.ft_transfer(accounts(1), 1.into(), None);
+        let initial_storage_usage = env::storage_usage();
+        contract.ft_transfer(accounts(1), 1.into(), Some("x".repeat(10_000)));
+        assert_eq!(env::storage_usage(), initial_storage_usage);
+    }
+
+    #[test]
+    #[should_panic(expected = "The memo is too long")]
+    fn test_memo_bloat_in_pending_transfer() {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_transfer_confirmation(Some(1_000.into()));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(1), 1.into(), Some("x".repeat(10_000)));
+    }
 }
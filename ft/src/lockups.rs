@@ -0,0 +1,144 @@
+//! Voluntary time-lockups.
+//!
+//! Any holder can lock part of its own balance until a given block timestamp, e.g. to prove its
+//! commitment. Locked tokens stay in the holder's balance but can't be transferred or burnt
+//! until the lockup ends. Locking more while a lockup is running adds to it and can only push the
+//! unlock time further. An ended lockup no longer restricts anything, and its record is removed on
+//! the holder's next transfer, refunding the storage the holder paid for it.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::FtTokensLocked;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Lockup {
+    pub amount: U128,
+    /// Block timestamp (in nanoseconds) from which the tokens are liquid again.
+    pub unlock_at: U64,
+}
+
+impl Lockup {
+    fn is_active(&self) -> bool {
+        env::block_timestamp() < self.unlock_at.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `amount` of the caller's balance until `unlock_timestamp`. The attached deposit pays
+    /// for storing the lockup.
+    #[payable]
+    pub fn lock_tokens(&mut self, amount: U128, unlock_timestamp: U64) -> Lockup {
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(
+            unlock_timestamp.0 > env::block_timestamp(),
+            "The unlock timestamp must be in the future"
+        );
+        let account_id = env::predecessor_account_id();
+        let balance = self.token.internal_unwrap_balance_of(&account_id);
+        let lockup = match self.lockups.get(&account_id).filter(Lockup::is_active) {
+            Some(lockup) => Lockup {
+                amount: U128(lockup.amount.0 + amount.0),
+                unlock_at: U64(lockup.unlock_at.0.max(unlock_timestamp.0)),
+            },
+            None => Lockup { amount, unlock_at: unlock_timestamp },
+        };
+        require!(lockup.amount.0 <= balance, "The amount exceeds the account's balance");
+        let initial_storage_usage = env::storage_usage();
+        self.lockups.insert(&account_id, &lockup);
+        FtTokensLocked { account_id: &account_id, amount: &amount, unlock_at: lockup.unlock_at }
+            .emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        lockup
+    }
+
+    pub fn lockup_of(&self, account_id: AccountId) -> Option<Lockup> {
+        self.lockups.get(&account_id).filter(Lockup::is_active)
+    }
+
+    pub fn locked_balance_of(&self, account_id: AccountId) -> U128 {
+        self.internal_locked_balance(&account_id).into()
+    }
+
+    /// Returns the part of `account_id`'s balance that isn't locked.
+    pub fn liquid_balance_of(&self, account_id: AccountId) -> U128 {
+        let balance = self.token.accounts.get(&account_id).unwrap_or(0);
+        balance.saturating_sub(self.internal_locked_balance(&account_id)).into()
+    }
+}
+
+impl Contract {
+    fn internal_locked_balance(&self, account_id: &AccountId) -> Balance {
+        self.lockups.get(account_id).filter(Lockup::is_active).map_or(0, |lockup| lockup.amount.0)
+    }
+
+    /// Panics if `amount` would dip into the locked or partially frozen part of the balance.
+    pub(crate) fn internal_assert_unlocked(&self, account_id: &AccountId, amount: Balance) {
+        let locked = self.internal_locked_balance(account_id);
+        if locked == 0 {
+            return;
+        }
+        let frozen = self.frozen_accounts.get(account_id).flatten().unwrap_or(0);
+        let balance = self.token.accounts.get(account_id).unwrap_or(0);
+        require!(
+            amount <= balance.saturating_sub(locked + frozen),
+            "The amount exceeds the account's unlocked balance"
+        );
+    }
+
+    /// Removes the lockup of `account_id` once it has ended.
+    pub(crate) fn internal_clear_ended_lockup(&mut self, account_id: &AccountId) {
+        if self.lockups.get(account_id).map_or(true, |lockup| lockup.is_active()) {
+            return;
+        }
+        let initial_storage_usage = env::storage_usage();
+        self.lockups.remove(account_id);
+        refund_released_storage(account_id.clone(), initial_storage_usage - env::storage_usage());
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const UNLOCK_AT: u64 = 1_000;
+
+    /// Locks all but 100 of the tokens of accounts(2) until `UNLOCK_AT`.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.lock_tokens((TOTAL_SUPPLY - 100).into(), UNLOCK_AT.into());
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the account's unlocked balance")]
+    fn test_transfer_of_locked_tokens() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.liquid_balance_of(accounts(2)).0, 100);
+        contract.ft_transfer(accounts(0), 101.into(), None);
+    }
+
+    #[test]
+    fn test_transfer_after_unlock() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(UNLOCK_AT).build());
+        assert_eq!(contract.locked_balance_of(accounts(2)).0, 0);
+        contract.ft_transfer(accounts(0), 1000.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 1000);
+        assert!(contract.lockups.get(&accounts(2)).is_none());
+    }
+}
@@ -0,0 +1,365 @@
+//! Point-of-sale merchant mode.
+//!
+//! A merchant registers a payout account and a settlement period. Customers pay it with
+//! `ft_pay_merchant`, which holds the tokens in the contract as the merchant's pending balance
+//! and records the payment. The pending balance is settled to the payout account by
+//! `settle_merchant`, which the merchant can call at any time and anyone else once the settlement
+//! period has passed since the last settlement. Each merchant keeps a report of its sales over the
+//! last `SALES_REPORT_DAYS` days.
+//!
+//! A merchant can refund a payment, fully or in parts, to the payer that made it. Refunds come out
+//! of the pending balance first and out of the merchant's own balance for the rest, and each one
+//! emits an `ft_merchant_refund` event carrying the id of the payment.
+//!
+//! The merchant's attached deposit pays for its record, whose size doesn't change afterwards, and
+//! each customer pays for storing its payment record.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::chargebacks::Dispute;
+use crate::events::{FtMerchantPayment, FtMerchantRefund, FtMerchantSettled};
+use crate::utils::{assert_stored_memo, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Number of days covered by a merchant's sales report.
+const SALES_REPORT_DAYS: u64 = 30;
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct DailySales {
+    /// Days since the Unix epoch.
+    pub day: u64,
+    pub amount: U128,
+    pub payments: u32,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Merchant {
+    pub payout_id: AccountId,
+    /// Minimum time (in nanoseconds) between settlements triggered by other accounts.
+    pub settlement_period: U64,
+    /// Block timestamp (in nanoseconds) of the last settlement.
+    pub last_settled_at: U64,
+    /// Payments received since the last settlement, held by the contract.
+    pub pending: U128,
+    /// How long (in nanoseconds) customers can dispute a payment, zero if the merchant isn't in
+    /// the chargeback program.
+    pub dispute_window: U64,
+    /// Tokens deposited by the merchant to cover reversed payments, held by the contract.
+    pub bond: U128,
+    pub open_disputes: u32,
+    /// Block timestamp (in nanoseconds) from which none of the payments made so far can be
+    /// disputed.
+    pub disputable_until: U64,
+    /// Ring of the last `SALES_REPORT_DAYS` days, indexed by the day modulo its length.
+    #[serde(skip)]
+    daily_sales: Vec<DailySales>,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct MerchantPayment {
+    pub merchant_id: AccountId,
+    pub payer_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    /// Block timestamp (in nanoseconds) of the payment.
+    pub paid_at: U64,
+    /// Amount already refunded to the payer, including reversed chargebacks.
+    pub refunded: U128,
+    pub dispute: Dispute,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers the caller as a merchant settling to `payout_id` at most every
+    /// `settlement_period` nanoseconds, or updates its settings. The attached deposit pays for
+    /// the merchant record.
+    #[payable]
+    pub fn register_merchant(&mut self, payout_id: AccountId, settlement_period: U64) {
+        let merchant_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&payout_id);
+        let initial_storage_usage = env::storage_usage();
+        let merchant = match self.merchants.get(&merchant_id) {
+            Some(merchant) => Merchant { payout_id, settlement_period, ..merchant },
+            None => Merchant {
+                payout_id,
+                settlement_period,
+                last_settled_at: env::block_timestamp().into(),
+                pending: U128(0),
+                dispute_window: U64(0),
+                bond: U128(0),
+                open_disputes: 0,
+                disputable_until: U64(0),
+                daily_sales: (0..SALES_REPORT_DAYS)
+                    .map(|_| DailySales { day: 0, amount: U128(0), payments: 0 })
+                    .collect(),
+            },
+        };
+        self.merchants.insert(&merchant_id, &merchant);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Settles the pending balance and removes the caller's merchant record, refunding its
+    /// storage.
+    #[payable]
+    pub fn unregister_merchant(&mut self) {
+        assert_one_yocto();
+        let merchant_id = env::predecessor_account_id();
+        let merchant = self.internal_unwrap_merchant(&merchant_id);
+        require!(merchant.bond.0 == 0, "Withdraw the chargeback bond first");
+        require!(merchant.open_disputes == 0, "The merchant has open disputes");
+        self.internal_settle_merchant(&merchant_id, merchant);
+        let initial_storage_usage = env::storage_usage();
+        self.merchants.remove(&merchant_id);
+        refund_released_storage(merchant_id, initial_storage_usage - env::storage_usage());
+    }
+
+    /// Pays `amount` to `merchant_id` and returns the id of the payment. The attached deposit
+    /// pays for the payment record.
+    #[payable]
+    pub fn ft_pay_merchant(
+        &mut self,
+        merchant_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) -> U64 {
+        require!(amount.0 > 0, "The amount should be a positive number");
+        assert_stored_memo(&memo);
+        let mut merchant = self.internal_unwrap_merchant(&merchant_id);
+        let payer_id = env::predecessor_account_id();
+        require!(payer_id != merchant_id, "The merchant can't pay itself");
+        require!(
+            !self.internal_exceeds_soft_limit(&payer_id, amount.into()),
+            "The amount exceeds the soft limit, use ft_transfer to queue it for approval"
+        );
+        self.internal_assert_not_blacklisted(&payer_id, &merchant_id);
+        let initial_storage_usage = env::storage_usage();
+        let amount = U128(self.internal_charge_fee(&payer_id, &merchant_id, amount.0));
+        self.internal_hold(&payer_id, amount.into(), memo.as_deref().unwrap_or("merchant payment"));
+
+        merchant.pending = U128(merchant.pending.0 + amount.0);
+        let now = env::block_timestamp();
+        if merchant.dispute_window.0 > 0 {
+            merchant.disputable_until =
+                U64(merchant.disputable_until.0.max(now + merchant.dispute_window.0 + 1));
+        }
+        let day = now / NANOS_PER_DAY;
+        let sales = &mut merchant.daily_sales[(day % SALES_REPORT_DAYS) as usize];
+        if sales.day != day {
+            *sales = DailySales { day, amount: U128(0), payments: 0 };
+        }
+        sales.amount = U128(sales.amount.0 + amount.0);
+        sales.payments += 1;
+        self.merchants.insert(&merchant_id, &merchant);
+
+        let payment_id = self.next_merchant_payment_id;
+        self.next_merchant_payment_id += 1;
+        self.merchant_payments.insert(
+            &payment_id,
+            &MerchantPayment {
+                merchant_id: merchant_id.clone(),
+                payer_id: payer_id.clone(),
+                amount,
+                memo,
+                paid_at: now.into(),
+                refunded: U128(0),
+                dispute: Dispute::None,
+            },
+        );
+        FtMerchantPayment {
+            payment_id: payment_id.into(),
+            merchant_id: &merchant_id,
+            payer_id: &payer_id,
+            amount: &amount,
+        }
+        .emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        payment_id.into()
+    }
+
+    /// Returns `amount` of the payment `payment_id` to its payer. Only the merchant that received
+    /// the payment can refund it.
+    #[payable]
+    pub fn refund(&mut self, payment_id: U64, amount: U128) {
+        assert_one_yocto();
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let mut payment = self.internal_unwrap_merchant_payment(payment_id.0);
+        let merchant_id = env::predecessor_account_id();
+        require!(payment.merchant_id == merchant_id, "Only the merchant can refund the payment");
+        require!(
+            amount.0 <= payment.amount.0 - payment.refunded.0,
+            "The refund exceeds the unrefunded amount of the payment"
+        );
+        payment.refunded = U128(payment.refunded.0 + amount.0);
+        self.merchant_payments.insert(&payment_id.0, &payment);
+
+        let mut merchant = self.internal_unwrap_merchant(&merchant_id);
+        let from_pending = amount.0.min(merchant.pending.0);
+        merchant.pending = U128(merchant.pending.0 - from_pending);
+        self.merchants.insert(&merchant_id, &merchant);
+        if amount.0 > from_pending {
+            self.internal_hold(&merchant_id, amount.0 - from_pending, "merchant refund");
+        }
+        if self.token.accounts.contains_key(&payment.payer_id) {
+            self.internal_release(&payment.payer_id, amount.0, "merchant refund");
+        } else {
+            self.internal_park_refund(payment.payer_id.clone(), amount.0);
+        }
+        FtMerchantRefund {
+            payment_id,
+            merchant_id: &merchant_id,
+            payer_id: &payment.payer_id,
+            amount: &amount,
+        }
+        .emit();
+    }
+
+    /// Moves the pending balance of `merchant_id` to its payout account. Other accounts than the
+    /// merchant can only settle once the settlement period has passed.
+    pub fn settle_merchant(&mut self, merchant_id: AccountId) -> U128 {
+        let merchant = self.internal_unwrap_merchant(&merchant_id);
+        require!(
+            env::predecessor_account_id() == merchant_id
+                || env::block_timestamp()
+                    >= merchant.last_settled_at.0 + merchant.settlement_period.0,
+            "The settlement period hasn't passed yet"
+        );
+        self.internal_settle_merchant(&merchant_id, merchant)
+    }
+
+    pub fn merchant(&self, merchant_id: AccountId) -> Option<Merchant> {
+        self.merchants.get(&merchant_id)
+    }
+
+    pub fn merchant_payment(&self, payment_id: U64) -> Option<MerchantPayment> {
+        self.merchant_payments.get(&payment_id.0)
+    }
+
+    /// Returns the sales of `merchant_id` over the last `SALES_REPORT_DAYS` days, oldest first.
+    /// Days without sales are left out.
+    pub fn merchant_sales_report(&self, merchant_id: AccountId) -> Vec<DailySales> {
+        let first_day =
+            (env::block_timestamp() / NANOS_PER_DAY).saturating_sub(SALES_REPORT_DAYS - 1);
+        let mut report: Vec<DailySales> = self
+            .internal_unwrap_merchant(&merchant_id)
+            .daily_sales
+            .into_iter()
+            .filter(|sales| sales.payments > 0 && sales.day >= first_day)
+            .collect();
+        report.sort_by_key(|sales| sales.day);
+        report
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_unwrap_merchant(&self, merchant_id: &AccountId) -> Merchant {
+        self.merchants
+            .get(merchant_id)
+            .unwrap_or_else(|| env::panic_str("The merchant is not registered"))
+    }
+
+    pub(crate) fn internal_unwrap_merchant_payment(&self, payment_id: u64) -> MerchantPayment {
+        self.merchant_payments
+            .get(&payment_id)
+            .unwrap_or_else(|| env::panic_str("The payment doesn't exist"))
+    }
+
+    fn internal_settle_merchant(
+        &mut self,
+        merchant_id: &AccountId,
+        mut merchant: Merchant,
+    ) -> U128 {
+        let amount = merchant.pending;
+        merchant.pending = U128(0);
+        merchant.last_settled_at = env::block_timestamp().into();
+        self.merchants.insert(merchant_id, &merchant);
+        if amount.0 > 0 {
+            self.internal_release(&merchant.payout_id, amount.0, "merchant settlement");
+        }
+        FtMerchantSettled { merchant_id, payout_id: &merchant.payout_id, amount: &amount }.emit();
+        amount
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const PERIOD: u64 = 1_000;
+
+    /// Registers accounts(1) as a merchant paying out to itself, and has accounts(2) pay it 100.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(env::storage_byte_cost() * 2000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.register_merchant(accounts(1), PERIOD.into());
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_pay_merchant(accounts(1), 100.into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_pay_merchant() {
+        let (_, contract) = setup();
+        assert_eq!(contract.merchant(accounts(1)).unwrap().pending.0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.merchant_payment(0.into()).unwrap().payer_id, accounts(2));
+        let report = contract.merchant_sales_report(accounts(1));
+        assert_eq!(report.len(), 1);
+        assert_eq!(report[0].amount.0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The settlement period hasn't passed yet")]
+    fn test_early_settlement_by_other_account() {
+        let (_, mut contract) = setup();
+        contract.settle_merchant(accounts(1));
+    }
+
+    #[test]
+    fn test_settlement_after_period() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(PERIOD).build());
+        assert_eq!(contract.settle_merchant(accounts(1)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert_eq!(contract.merchant(accounts(1)).unwrap().pending.0, 0);
+    }
+
+    #[test]
+    fn test_refund_from_pending_balance() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.refund(0.into(), 40.into());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 60);
+        assert_eq!(contract.merchant(accounts(1)).unwrap().pending.0, 60);
+        assert_eq!(contract.merchant_payment(0.into()).unwrap().refunded.0, 40);
+    }
+
+    #[test]
+    #[should_panic(expected = "The refund exceeds the unrefunded amount of the payment")]
+    fn test_refund_above_payment() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.refund(0.into(), 60.into());
+        contract.refund(0.into(), 60.into());
+    }
+}
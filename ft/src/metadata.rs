@@ -0,0 +1,159 @@
+//! Owner updates of the token metadata.
+//!
+//! The metadata can be replaced as a whole with `update_ft_metadata` or field by field with the
+//! granular setters. Every update is re-validated and announced with an `ft_metadata_updated`
+//! event. The decimals can never change, since that would rescale every balance. If an update
+//! grows the stored metadata, the owner's attached deposit pays for the extra storage; if it
+//! shrinks it, the released storage is refunded to the owner.
+
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, near_bindgen, require};
+
+use crate::events::{FtMetadataUpdated, FtReferenceUpdated};
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn update_ft_metadata(&mut self, metadata: FungibleTokenMetadata) {
+        let decimals = self.metadata.get().unwrap().decimals;
+        require!(metadata.decimals == decimals, "The decimals can't be changed");
+        self.internal_update_metadata(
+            &["name", "symbol", "icon", "reference", "reference_hash"],
+            |current| *current = metadata,
+        );
+    }
+
+    #[payable]
+    pub fn set_icon(&mut self, icon: Option<String>) {
+        self.internal_update_metadata(&["icon"], |metadata| metadata.icon = icon);
+    }
+
+    #[payable]
+    pub fn set_name(&mut self, name: String) {
+        self.internal_update_metadata(&["name"], |metadata| metadata.name = name);
+    }
+
+    #[payable]
+    pub fn set_symbol(&mut self, symbol: String) {
+        self.internal_update_metadata(&["symbol"], |metadata| metadata.symbol = symbol);
+    }
+
+    /// Points the metadata to an off-chain document. `reference_hash` is the SHA-256 hash of the
+    /// document, so that anyone fetching it can check it wasn't altered.
+    #[payable]
+    pub fn set_reference(&mut self, reference: String, reference_hash: Base64VecU8) {
+        require!(!reference.is_empty(), "The reference can't be empty");
+        require!(reference_hash.0.len() == 32, "The reference hash must be 32 bytes");
+        self.internal_update_metadata(&["reference", "reference_hash"], |metadata| {
+            metadata.reference = Some(reference.clone());
+            metadata.reference_hash = Some(reference_hash.clone());
+        });
+        FtReferenceUpdated {
+            owner_id: &self.owner_id,
+            reference: &reference,
+            reference_hash: &reference_hash,
+        }
+        .emit();
+    }
+
+    /// Checks that `document` matches the reference hash stored in the metadata.
+    pub fn verify_reference_hash(&self, document: Base64VecU8) -> bool {
+        self.metadata
+            .get()
+            .unwrap()
+            .reference_hash
+            .map_or(false, |hash| hash.0 == env::sha256(&document.0))
+    }
+}
+
+impl Contract {
+    /// Applies `update` to the metadata on behalf of the owner, who must attach at least 1
+    /// yoctoNEAR.
+    fn internal_update_metadata(
+        &mut self,
+        fields: &[&str],
+        update: impl FnOnce(&mut FungibleTokenMetadata),
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        self.assert_owner();
+        let mut metadata = self.metadata.get().unwrap();
+        update(&mut metadata);
+        metadata.assert_valid();
+
+        let initial_storage_usage = env::storage_usage();
+        self.metadata.set(&metadata);
+        let storage_usage = env::storage_usage();
+        if storage_usage > initial_storage_usage {
+            refund_deposit(storage_usage - initial_storage_usage);
+        } else {
+            refund_released_storage(
+                env::predecessor_account_id(),
+                initial_storage_usage - storage_usage,
+            );
+        }
+        FtMetadataUpdated { owner_id: &self.owner_id, fields }.emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::FungibleTokenMetadataProvider;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    #[test]
+    fn test_set_name_and_symbol() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.set_name("Renamed token".to_string());
+        contract.set_symbol("RNM".to_string());
+        let metadata = contract.ft_metadata();
+        assert_eq!(metadata.name, "Renamed token");
+        assert_eq!(metadata.symbol, "RNM");
+    }
+
+    #[test]
+    fn test_set_reference() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        let document = b"{\"description\":\"Example token\"}".to_vec();
+        contract.set_reference(
+            "https://example.com/token.json".to_string(),
+            env::sha256(&document).into(),
+        );
+        assert!(contract.verify_reference_hash(document.into()));
+        assert!(!contract.verify_reference_hash(b"tampered".to_vec().into()));
+    }
+
+    #[test]
+    #[should_panic(expected = "The reference hash must be 32 bytes")]
+    fn test_set_reference_with_short_hash() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_reference("https://example.com/token.json".to_string(), vec![0; 16].into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The decimals can't be changed")]
+    fn test_update_decimals() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        let mut metadata = contract.ft_metadata();
+        metadata.decimals = 18;
+        contract.update_ft_metadata(metadata);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_set_icon_by_non_owner() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.set_icon(None);
+    }
+}
@@ -0,0 +1,158 @@
+//! Account migration for users rotating compromised or legacy accounts.
+//!
+//! The new account first accepts the migration from the old one with `accept_migration`. The old
+//! account then calls `migrate_account`, which moves its whole balance and its operator approvals
+//! to the new account in one step. The old account stays registered with a zero balance and can
+//! be unregistered afterwards to get its storage deposit back.
+//!
+//! Vesting grants, allowances, lockups and savings goals are not migrated. An account with any of
+//! them can't migrate until they are claimed, revoked, used up or ended.
+
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::FtAccountMigrated;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Consents to receive the migration of `from_account_id` into the caller, or withdraws the
+    /// consent when `None` is passed. The attached deposit pays for storing the consent.
+    #[payable]
+    pub fn accept_migration(&mut self, from_account_id: Option<AccountId>) {
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        match from_account_id {
+            Some(from_account_id) => {
+                require!(from_account_id != account_id, "Can't migrate an account into itself");
+                self.migration_consents.insert(&account_id, &from_account_id);
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                assert_one_yocto();
+                self.migration_consents.remove(&account_id);
+                refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+            }
+        }
+    }
+
+    /// Moves the caller's balance and operator approvals to `new_account_id`, which must have
+    /// accepted the migration beforehand. Accounts with a transfer policy can't migrate until the
+    /// approver removes the policy, since migrating would bypass its soft limit, nor while they
+    /// have vesting grants, allowances, an active lockup or savings goals.
+    #[payable]
+    pub fn migrate_account(&mut self, new_account_id: AccountId) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        require!(
+            self.migration_consents.get(&new_account_id).as_ref() == Some(&account_id),
+            "The new account hasn't accepted the migration"
+        );
+        require!(
+            !self.transfer_policies.contains_key(&account_id),
+            "Accounts with a transfer policy can't be migrated"
+        );
+        require!(!self.vestings.contains_key(&account_id), "The account has vesting schedules");
+        require!(!self.allowance_spenders.contains_key(&account_id), "The account has allowances");
+        self.internal_clear_ended_lockup(&account_id);
+        require!(!self.lockups.contains_key(&account_id), "The account has an active lockup");
+        require!(!self.savings_goals.contains_key(&account_id), "The account has savings goals");
+        let initial_storage_usage = env::storage_usage();
+        self.migration_consents.remove(&new_account_id);
+
+        if let Some(approvals) = self.operators.remove(&account_id) {
+            require!(
+                !self.operators.contains_key(&new_account_id),
+                "The new account already has operator approvals"
+            );
+            self.operators.insert(&new_account_id, &approvals);
+        }
+        let amount = self.token.internal_unwrap_balance_of(&account_id);
+        if amount > 0 {
+            self.internal_assert_transfer_allowed(&account_id, &new_account_id, amount);
+            self.token.internal_transfer(
+                &account_id,
+                &new_account_id,
+                amount,
+                Some("account migration".to_string()),
+            );
+        }
+        FtAccountMigrated {
+            old_account_id: &account_id,
+            new_account_id: &new_account_id,
+            amount: &U128(amount),
+        }
+        .emit();
+        refund_released_storage(
+            new_account_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+    }
+
+    /// Returns the account whose migration into `account_id` was accepted, if any.
+    pub fn migration_consent_of(&self, account_id: AccountId) -> Option<AccountId> {
+        self.migration_consents.get(&account_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Registers accounts(1) as the new account of accounts(2).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, contract) = setup_contract(&[accounts(1)]);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_migrate_account() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.accept_migration(Some(accounts(2)));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.migrate_account(accounts(1));
+
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, 0);
+        assert!(contract.migration_consent_of(accounts(1)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The new account hasn't accepted the migration")]
+    fn test_migrate_account_without_consent() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.migrate_account(accounts(1));
+    }
+
+    #[test]
+    #[should_panic(expected = "The account has allowances")]
+    fn test_migrate_account_with_allowances() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.accept_migration(Some(accounts(2)));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_approve(accounts(3), 100.into(), None);
+        testing_env!(context.attached_deposit(1).build());
+        contract.migrate_account(accounts(1));
+    }
+}
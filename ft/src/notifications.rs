@@ -0,0 +1,105 @@
+//! Receipt-of-funds notifications for plain transfers.
+//!
+//! A receiver can register a contract that gets a low-gas `ft_on_deposit` call whenever the
+//! receiver is credited by a plain transfer, so custodial systems can credit deposits without
+//! running an indexer. The notification is fire-and-forget: its result is not checked and it
+//! can't undo the transfer.
+
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, ext_contract, near_bindgen, AccountId, Balance, Gas};
+
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+const GAS_FOR_DEPOSIT_NOTIFICATION: Gas = Gas(5_000_000_000_000);
+
+/// Interface of the contracts registered as deposit notifiers.
+#[allow(dead_code)]
+#[ext_contract(ext_deposit_notifier)]
+pub trait DepositNotifier {
+    fn ft_on_deposit(
+        &mut self,
+        sender_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    );
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers `notifier_id` to be notified of the caller's incoming transfers, or stops the
+    /// notifications when `None` is passed. The attached deposit pays for storing the setting.
+    #[payable]
+    pub fn set_deposit_notifier(&mut self, notifier_id: Option<AccountId>) {
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        match notifier_id {
+            Some(notifier_id) => {
+                self.deposit_notifiers.insert(&account_id, &notifier_id);
+                refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+            }
+            None => {
+                assert_one_yocto();
+                self.deposit_notifiers.remove(&account_id);
+                refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+            }
+        }
+    }
+
+    pub fn deposit_notifier_of(&self, account_id: AccountId) -> Option<AccountId> {
+        self.deposit_notifiers.get(&account_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_notify_deposit(
+        &self,
+        sender_id: &AccountId,
+        receiver_id: &AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) {
+        if let Some(notifier_id) = self.deposit_notifiers.get(receiver_id) {
+            ext_deposit_notifier::ext(notifier_id)
+                .with_static_gas(GAS_FOR_DEPOSIT_NOTIFICATION)
+                .ft_on_deposit(sender_id.clone(), receiver_id.clone(), amount.into(), memo);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::mock::VmAction;
+    use near_sdk::test_utils::{accounts, get_created_receipts};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    #[test]
+    fn test_transfer_notifies_receiver() {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_deposit_notifier(Some(accounts(4)));
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+
+        let receipts = get_created_receipts();
+        let notification = receipts.last().unwrap();
+        assert_eq!(notification.receiver_id, accounts(4));
+        assert!(matches!(
+            &notification.actions[0],
+            VmAction::FunctionCall { function_name, .. } if function_name == "ft_on_deposit"
+        ));
+    }
+}
@@ -0,0 +1,251 @@
+//! Operator approvals for full-account delegation.
+//!
+//! An owner can approve an operator (e.g. an account-abstraction wallet or a portfolio manager
+//! contract) to transfer its tokens with `ft_operator_transfer`. An approval is either unlimited
+//! or capped to a total amount that is used up by the operator's transfers. Approvals can be
+//! revoked one by one or all at once. An operator with an unlimited approval can also move the
+//! owner's tokens with `ft_transfer_from` without an allowance. Every approval and revocation
+//! emits an `ft_operator_approval` event.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::FtOperatorApproval;
+use crate::utils::{refund_deposit, refund_released_storage, refund_storage_change};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of operators a single owner can approve.
+const MAX_OPERATORS: usize = 20;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct OperatorApproval {
+    pub operator_id: AccountId,
+    /// Amount the operator can still move, or `None` if the approval is unlimited.
+    pub cap: Option<U128>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Approves `operator_id` to transfer the caller's tokens, up to `cap` in total or without
+    /// limit if `cap` is `None`. Replaces an existing approval of the same operator. Requires at
+    /// least 1 yoctoNEAR attached; the attached deposit pays for storing the approval.
+    #[payable]
+    pub fn approve_operator(&mut self, operator_id: AccountId, cap: Option<U128>) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        let owner_id = env::predecessor_account_id();
+        require!(owner_id != operator_id, "The owner can't be its own operator");
+        self.token.internal_unwrap_balance_of(&owner_id);
+        let initial_storage_usage = env::storage_usage();
+        let mut approvals = self.operators.get(&owner_id).unwrap_or_default();
+        approvals.retain(|approval| approval.operator_id != operator_id);
+        require!(approvals.len() < MAX_OPERATORS, "Too many operators");
+        FtOperatorApproval { owner_id: &owner_id, operator_id: &operator_id, approved: true }
+            .emit();
+        approvals.push(OperatorApproval { operator_id, cap });
+        self.operators.insert(&owner_id, &approvals);
+        refund_storage_change(owner_id, initial_storage_usage);
+    }
+
+    /// Approves `operator_id` to move any amount of the caller's tokens, or revokes its approval.
+    #[payable]
+    pub fn set_operator(&mut self, operator_id: AccountId, approved: bool) {
+        if approved {
+            self.approve_operator(operator_id, None);
+        } else {
+            self.revoke_operator(operator_id);
+        }
+    }
+
+    /// Revokes the approval of `operator_id` and refunds its storage to the caller.
+    #[payable]
+    pub fn revoke_operator(&mut self, operator_id: AccountId) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        let mut approvals = self.operators.get(&owner_id).unwrap_or_default();
+        let len = approvals.len();
+        approvals.retain(|approval| approval.operator_id != operator_id);
+        require!(approvals.len() < len, "The operator is not approved");
+        FtOperatorApproval { owner_id: &owner_id, operator_id: &operator_id, approved: false }
+            .emit();
+        self.internal_save_operators(owner_id, approvals);
+    }
+
+    /// Revokes every operator approval of the caller at once.
+    #[payable]
+    pub fn revoke_all_operators(&mut self) {
+        assert_one_yocto();
+        let owner_id = env::predecessor_account_id();
+        for approval in self.operators.get(&owner_id).unwrap_or_default() {
+            FtOperatorApproval {
+                owner_id: &owner_id,
+                operator_id: &approval.operator_id,
+                approved: false,
+            }
+            .emit();
+        }
+        self.internal_save_operators(owner_id, vec![]);
+    }
+
+    /// Transfers `amount` of `owner_id`'s tokens to `receiver_id` on behalf of the owner. The
+    /// caller must be an approved operator of the owner, with enough of its cap left.
+    #[payable]
+    pub fn ft_operator_transfer(
+        &mut self,
+        owner_id: AccountId,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(
+            !self.approval_freezes.contains_key(&owner_id),
+            "The owner's approvals are frozen"
+        );
+        let operator_id = env::predecessor_account_id();
+        let mut approvals = self.operators.get(&owner_id).unwrap_or_default();
+        let approval = approvals
+            .iter_mut()
+            .find(|approval| approval.operator_id == operator_id)
+            .unwrap_or_else(|| env::panic_str("The caller is not an approved operator"));
+        if let Some(cap) = approval.cap.as_mut() {
+            require!(cap.0 >= amount.0, "The amount exceeds the operator cap");
+            cap.0 -= amount.0;
+            self.operators.insert(&owner_id, &approvals);
+        }
+        require!(
+            !self.internal_exceeds_soft_limit(&owner_id, amount.into()),
+            "The amount exceeds the owner's soft limit"
+        );
+        refund_deposit(self.internal_transfer(&owner_id, &receiver_id, amount.into(), memo));
+    }
+
+    pub fn operator_approval(
+        &self,
+        owner_id: AccountId,
+        operator_id: AccountId,
+    ) -> Option<OperatorApproval> {
+        self.operators
+            .get(&owner_id)
+            .and_then(|approvals| approvals.into_iter().find(|a| a.operator_id == operator_id))
+    }
+
+    /// Returns whether `operator_id` can move any amount of `owner_id`'s tokens.
+    pub fn is_operator(&self, owner_id: AccountId, operator_id: AccountId) -> bool {
+        self.internal_is_unlimited_operator(&owner_id, &operator_id)
+    }
+
+    /// Returns up to `limit` of the approvals of `owner_id`, starting at `from_index`.
+    pub fn operators_of(
+        &self,
+        owner_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<OperatorApproval> {
+        self.operators
+            .get(&owner_id)
+            .unwrap_or_default()
+            .into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(MAX_OPERATORS, |limit| limit as usize))
+            .collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_is_unlimited_operator(
+        &self,
+        owner_id: &AccountId,
+        operator_id: &AccountId,
+    ) -> bool {
+        self.operators.get(owner_id).map_or(false, |approvals| {
+            approvals.iter().any(|a| &a.operator_id == operator_id && a.cap.is_none())
+        })
+    }
+
+    fn internal_save_operators(&mut self, owner_id: AccountId, approvals: Vec<OperatorApproval>) {
+        let initial_storage_usage = env::storage_usage();
+        if approvals.is_empty() {
+            self.operators.remove(&owner_id);
+        } else {
+            self.operators.insert(&owner_id, &approvals);
+        }
+        refund_released_storage(owner_id, initial_storage_usage - env::storage_usage());
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Approves accounts(3) as an operator of accounts(2) with the given cap.
+    fn setup(cap: Option<U128>) -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.approve_operator(accounts(3), cap);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(3))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_operator_transfer_uses_cap() {
+        let (_, mut contract) = setup(Some(U128(300)));
+        contract.ft_operator_transfer(accounts(2), accounts(0), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 100);
+        let approval = contract.operator_approval(accounts(2), accounts(3)).unwrap();
+        assert_eq!(approval.cap, Some(U128(200)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the operator cap")]
+    fn test_operator_transfer_above_cap() {
+        let (_, mut contract) = setup(Some(U128(300)));
+        contract.ft_operator_transfer(accounts(2), accounts(0), 301.into(), None);
+    }
+
+    #[test]
+    fn test_operator_transfer_from_without_allowance() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.set_operator(accounts(4), true);
+        assert!(contract.is_operator(accounts(2), accounts(4)));
+        assert_eq!(contract.operators_of(accounts(2), Some(1), None).len(), 1);
+
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(4)).build());
+        contract.ft_transfer_from(accounts(2), accounts(0), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The caller is not an approved operator")]
+    fn test_operator_transfer_after_revoke_all() {
+        let (mut context, mut contract) = setup(None);
+        testing_env!(context.predecessor_account_id(accounts(2)).build());
+        contract.revoke_all_operators();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.ft_operator_transfer(accounts(2), accounts(0), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+    fn test_unlimited_approval_without_deposit() {
+        let (mut context, mut contract) = setup(Some(100.into()));
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(2)).build());
+        contract.set_operator(accounts(3), true);
+    }
+}
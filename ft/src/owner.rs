@@ -0,0 +1,116 @@
+//! The owner of the token.
+//!
+//! The owner is initially the account passed as `owner_id` to `new`, which also receives the
+//! initial supply. It is the only account allowed to manage the token. Ownership is handed over
+//! in two steps: the owner proposes the next owner, which then has to accept. A mistyped account
+//! can't accept, so the owner can cancel the proposal or propose another account instead.
+
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::{FtOwnerChanged, FtOwnershipProposalCancelled, FtOwnershipProposed};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    pub fn get_owner(&self) -> AccountId {
+        self.owner_id.clone()
+    }
+
+    /// Returns the account proposed as the next owner, if any.
+    pub fn get_pending_owner(&self) -> Option<AccountId> {
+        self.pending_owner_id.clone()
+    }
+
+    /// Proposes `new_owner_id` as the next owner, replacing any previous proposal. Can only be
+    /// called by the owner.
+    #[payable]
+    pub fn propose_owner(&mut self, new_owner_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        require!(new_owner_id != self.owner_id, "The account is already the owner");
+        FtOwnershipProposed { owner_id: &self.owner_id, pending_owner_id: &new_owner_id }.emit();
+        self.pending_owner_id = Some(new_owner_id);
+    }
+
+    /// Makes the caller the owner. The caller must have been proposed by the owner.
+    #[payable]
+    pub fn accept_ownership(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        require!(
+            self.pending_owner_id.as_ref() == Some(&account_id),
+            "The caller is not the proposed owner"
+        );
+        FtOwnerChanged { old_owner_id: &self.owner_id, new_owner_id: &account_id }.emit();
+        self.owner_id = account_id;
+        self.pending_owner_id = None;
+    }
+
+    /// Withdraws the proposal of the next owner. Can only be called by the owner.
+    #[payable]
+    pub fn cancel_ownership_proposal(&mut self) {
+        assert_one_yocto();
+        self.assert_owner();
+        let pending_owner_id = self
+            .pending_owner_id
+            .take()
+            .unwrap_or_else(|| env::panic_str("No ownership proposal to cancel"));
+        FtOwnershipProposalCancelled {
+            owner_id: &self.owner_id,
+            pending_owner_id: &pending_owner_id,
+        }
+        .emit();
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_owner(&self) {
+        require!(
+            env::predecessor_account_id() == self.owner_id,
+            "Only the owner can call this method"
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::setup_contract;
+
+    /// accounts(2) owns the token and proposes accounts(3) as the next owner.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.propose_owner(accounts(3));
+        (context, contract)
+    }
+
+    #[test]
+    fn test_accept_ownership() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.accept_ownership();
+        assert_eq!(contract.get_owner(), accounts(3));
+        assert!(contract.get_pending_owner().is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "The caller is not the proposed owner")]
+    fn test_accept_cancelled_proposal() {
+        let (mut context, mut contract) = setup();
+        contract.cancel_ownership_proposal();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.accept_ownership();
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_propose_owner_by_non_owner() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(3)).build());
+        contract.propose_owner(accounts(3));
+    }
+}
@@ -0,0 +1,189 @@
+//! Pause switches for incident response.
+//!
+//! Each pausable feature can be suspended on its own, so an exploited receiver contract can be
+//! cut off by pausing `ft_transfer_call` alone while plain transfers keep working. `pause` and
+//! `unpause` switch every feature at once. View methods are never paused. Changing the switches
+//! is restricted to accounts with the pauser role.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require};
+
+use crate::events::{FtPaused, FtUnpaused};
+use crate::roles::{require_role, Role};
+use crate::{Contract, ContractExt};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Feature {
+    /// `ft_transfer` and every other way of moving tokens between accounts.
+    Transfer,
+    TransferCall,
+    Mint,
+    Burn,
+    /// `storage_deposit`, `storage_withdraw` and `storage_unregister`.
+    Storage,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 5] =
+        [Feature::Transfer, Feature::TransferCall, Feature::Mint, Feature::Burn, Feature::Storage];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Feature::Transfer => "transfer",
+            Feature::TransferCall => "transfer_call",
+            Feature::Mint => "mint",
+            Feature::Burn => "burn",
+            Feature::Storage => "storage",
+        }
+    }
+}
+
+/// The paused features, stored as a bitmask.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct PausedFeatures(u8);
+
+impl PausedFeatures {
+    pub fn contains(self, feature: Feature) -> bool {
+        self.0 & feature.bit() != 0
+    }
+
+    pub fn with(self, feature: Feature) -> Self {
+        Self(self.0 | feature.bit())
+    }
+
+    pub fn without(self, feature: Feature) -> Self {
+        Self(self.0 & !feature.bit())
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Pauses every feature that is not paused yet.
+    #[payable]
+    pub fn pause(&mut self) {
+        assert_one_yocto();
+        require_role!(self, Role::Pauser);
+        let features = self.internal_set_features_paused(&Feature::ALL, true);
+        require!(!features.is_empty(), "The contract is already paused");
+        FtPaused { account_id: &env::predecessor_account_id(), features }.emit();
+    }
+
+    /// Resumes every paused feature.
+    #[payable]
+    pub fn unpause(&mut self) {
+        assert_one_yocto();
+        require_role!(self, Role::Pauser);
+        let features = self.internal_set_features_paused(&Feature::ALL, false);
+        require!(!features.is_empty(), "The contract is not paused");
+        FtUnpaused { account_id: &env::predecessor_account_id(), features }.emit();
+    }
+
+    #[payable]
+    pub fn set_feature_paused(&mut self, feature: Feature, paused: bool) {
+        assert_one_yocto();
+        require_role!(self, Role::Pauser);
+        let features = self.internal_set_features_paused(&[feature], paused);
+        require!(!features.is_empty(), "The feature is already in that state");
+        let account_id = env::predecessor_account_id();
+        if paused {
+            FtPaused { account_id: &account_id, features }.emit();
+        } else {
+            FtUnpaused { account_id: &account_id, features }.emit();
+        }
+    }
+
+    pub fn paused_features(&self) -> Vec<Feature> {
+        Feature::ALL.iter().copied().filter(|&feature| self.paused.contains(feature)).collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn assert_not_paused(&self, feature: Feature) {
+        if self.paused.contains(feature) {
+            env::panic_str(&format!("The {} feature is paused", feature.name()));
+        }
+    }
+
+    /// Switches `features` and returns the ones whose state actually changed.
+    fn internal_set_features_paused(&mut self, features: &[Feature], paused: bool) -> Vec<Feature> {
+        let mut changed = Vec::new();
+        for &feature in features {
+            if self.paused.contains(feature) != paused {
+                self.paused =
+                    if paused { self.paused.with(feature) } else { self.paused.without(feature) };
+                changed.push(feature);
+            }
+        }
+        changed
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, TOTAL_SUPPLY};
+
+    /// Registers accounts(1) and leaves accounts(2), the owner, as the caller.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, contract) = setup_contract(&[accounts(1)]);
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The transfer feature is paused")]
+    fn test_transfer_while_paused() {
+        let (_, mut contract) = setup();
+        contract.pause();
+        assert_eq!(contract.paused_features(), Feature::ALL.to_vec());
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The storage feature is paused")]
+    fn test_unregister_while_paused() {
+        let (mut context, mut contract) = setup();
+        contract.pause();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.storage_unregister(None);
+    }
+
+    #[test]
+    fn test_transfer_after_unpause() {
+        let (_, mut contract) = setup();
+        contract.pause();
+        contract.unpause();
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The transfer_call feature is paused")]
+    fn test_pause_transfer_call_only() {
+        let (_, mut contract) = setup();
+        contract.set_feature_paused(Feature::TransferCall, true);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        contract.ft_transfer_call(accounts(1), 100.into(), None, String::new());
+    }
+
+    #[test]
+    #[should_panic(expected = "The caller doesn't have the pauser role")]
+    fn test_pause_by_non_pauser() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.pause();
+    }
+}
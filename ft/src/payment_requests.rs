@@ -0,0 +1,150 @@
+//! Signed payment requests for point-of-sale flows.
+//!
+//! A merchant builds the canonical payload of a request with `payment_request_payload`, signs it
+//! with its permit key (see `permit`) and hands the request and signature to the customer, e.g. as
+//! a QR code or deep link. The customer's wallet submits both to `ft_pay_request`, which checks the
+//! signature and the expiry before transferring the requested amount to the merchant, so the
+//! amount and memo can't be altered on the way. The payload starts with the
+//! `PAYMENT_REQUEST_DOMAIN` tag, so a payment request signature can't be used as a permit and the
+//! other way round.
+
+use near_sdk::borsh::{self, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance};
+
+use crate::permit::assert_valid_signature;
+use crate::utils::refund_deposit;
+use crate::{Contract, ContractExt};
+
+/// Tag at the start of every payment request.
+pub const PAYMENT_REQUEST_DOMAIN: &str = "nep-ft-payment-request";
+
+/// The message signed by the merchant to request a payment.
+#[derive(BorshSerialize)]
+pub struct PaymentRequest {
+    /// Always `PAYMENT_REQUEST_DOMAIN`.
+    pub domain: String,
+    pub contract_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: Balance,
+    pub memo: Option<String>,
+    /// Block timestamp (in nanoseconds) after which the request can't be paid.
+    pub expires_at: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the bytes `receiver_id` must sign with its permit key to request `amount`.
+    pub fn payment_request_payload(
+        &self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        expires_at: U64,
+    ) -> Base64VecU8 {
+        PaymentRequest {
+            domain: PAYMENT_REQUEST_DOMAIN.to_string(),
+            contract_id: env::current_account_id(),
+            receiver_id,
+            amount: amount.0,
+            memo,
+            expires_at: expires_at.0,
+        }
+        .try_to_vec()
+        .unwrap()
+        .into()
+    }
+
+    /// Pays the payment request signed by `receiver_id` from the caller's balance.
+    #[payable]
+    pub fn ft_pay_request(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        expires_at: U64,
+        signature: Base64VecU8,
+    ) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(env::block_timestamp() <= expires_at.0, "The payment request has expired");
+        let key = self
+            .permit_keys
+            .get(&receiver_id)
+            .unwrap_or_else(|| env::panic_str("The receiver has no permit key"));
+        let payload =
+            self.payment_request_payload(receiver_id.clone(), amount, memo.clone(), expires_at);
+        assert_valid_signature(&key.public_key, &payload.0, &signature);
+        let sender_id = env::predecessor_account_id();
+        refund_deposit(self.internal_ft_transfer(sender_id, receiver_id, amount.into(), memo));
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::convert::TryInto;
+
+    use ed25519_dalek::{ExpandedSecretKey, SecretKey};
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, CurveType};
+
+    use super::*;
+    use crate::tests::{register, setup_contract, TOTAL_SUPPLY};
+
+    const EXPIRES_AT: u64 = 1_000;
+
+    /// Registers accounts(1) as a merchant with a permit key and returns the signature of its
+    /// request for 100 tokens.
+    fn setup() -> (VMContextBuilder, Contract, Base64VecU8) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let public_key = ed25519_dalek::PublicKey::from(&secret);
+        let mut near_key = vec![CurveType::ED25519 as u8];
+        near_key.extend(public_key.as_bytes());
+        register(&mut context, &mut contract, accounts(1));
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.set_permit_key(near_key.try_into().unwrap());
+
+        let payload = contract.payment_request_payload(
+            accounts(1),
+            100.into(),
+            Some("order 42".to_string()),
+            EXPIRES_AT.into(),
+        );
+        let signature = ExpandedSecretKey::from(&secret).sign(&payload.0, &public_key);
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        (context, contract, signature.to_bytes().to_vec().into())
+    }
+
+    #[test]
+    fn test_pay_request() {
+        let (_, mut contract, signature) = setup();
+        let memo = Some("order 42".to_string());
+        contract.ft_pay_request(accounts(1), 100.into(), memo, EXPIRES_AT.into(), signature);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn test_pay_request_with_altered_amount() {
+        let (_, mut contract, signature) = setup();
+        let memo = Some("order 42".to_string());
+        contract.ft_pay_request(accounts(1), 10.into(), memo, EXPIRES_AT.into(), signature);
+    }
+
+    #[test]
+    fn test_payload_starts_with_domain() {
+        let (_, contract, _) = setup();
+        let payload = contract.payment_request_payload(accounts(1), 100.into(), None, 0.into());
+        let domain = PAYMENT_REQUEST_DOMAIN.to_string().try_to_vec().unwrap();
+        assert!(payload.0.starts_with(&domain));
+    }
+}
@@ -0,0 +1,217 @@
+//! Signature-based approvals (`permit`).
+//!
+//! An owner registers an ed25519 public key with `set_permit_key`. A relayer can then submit a
+//! `permit` signed with that key to set an allowance on the owner's behalf, paying for the
+//! transaction and for storing the allowance. The signed message is the Borsh encoding of
+//! [`PermitMessage`], which starts with the `PERMIT_DOMAIN` tag so the signature can't be passed
+//! off as another kind of signed message, and names this contract so a permit can't be replayed on
+//! another token.
+//! Each permit must use the owner's next nonce, and replacing the key keeps the nonce, so the
+//! nonces keep growing and a used permit can never be submitted again. The key is removed when the
+//! owner closes its account; a key set later starts at the current block timestamp, which is far
+//! above any nonce used before.
+
+use ed25519_dalek::Verifier;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::{env, near_bindgen, require, AccountId, Balance, CurveType, PublicKey};
+
+use crate::allowances::Allowance;
+use crate::utils::refund_deposit;
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize)]
+pub struct PermitKey {
+    pub public_key: PublicKey,
+    /// Nonce the next permit must use.
+    pub next_nonce: u64,
+}
+
+/// Tag at the start of every permit message.
+pub const PERMIT_DOMAIN: &str = "nep-ft-permit";
+
+/// The message signed by the owner to approve `spender_id`.
+#[derive(BorshSerialize)]
+pub struct PermitMessage {
+    /// Always `PERMIT_DOMAIN`.
+    pub domain: String,
+    pub contract_id: AccountId,
+    pub owner_id: AccountId,
+    pub spender_id: AccountId,
+    pub amount: Balance,
+    /// Block timestamp (in nanoseconds) after which the permit can't be used.
+    pub deadline: u64,
+    pub nonce: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers the ed25519 key that signs the caller's permits, replacing the previous one.
+    /// Requires at least 1 yoctoNEAR attached; the attached deposit pays for storing the key.
+    #[payable]
+    pub fn set_permit_key(&mut self, public_key: PublicKey) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        require!(public_key.curve_type() == CurveType::ED25519, "Only ed25519 keys are supported");
+        let owner_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&owner_id);
+        let next_nonce =
+            self.permit_keys.get(&owner_id).map_or(env::block_timestamp(), |key| key.next_nonce);
+        let initial_storage_usage = env::storage_usage();
+        self.permit_keys.insert(&owner_id, &PermitKey { public_key, next_nonce });
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Sets the allowance of `spender_id` over `owner_id`'s tokens to `amount`, as signed by the
+    /// owner's permit key.
+    #[payable]
+    pub fn permit(
+        &mut self,
+        owner_id: AccountId,
+        spender_id: AccountId,
+        amount: U128,
+        deadline: U64,
+        nonce: U64,
+        signature: Base64VecU8,
+    ) {
+        require!(env::block_timestamp() <= deadline.0, "The permit has expired");
+        require!(owner_id != spender_id, "The owner can't be its own spender");
+        let mut key = self
+            .permit_keys
+            .get(&owner_id)
+            .unwrap_or_else(|| env::panic_str("The owner has no permit key"));
+        require!(nonce.0 == key.next_nonce, "Invalid permit nonce");
+
+        let message = PermitMessage {
+            domain: PERMIT_DOMAIN.to_string(),
+            contract_id: env::current_account_id(),
+            owner_id: owner_id.clone(),
+            spender_id: spender_id.clone(),
+            amount: amount.0,
+            deadline: deadline.0,
+            nonce: nonce.0,
+        };
+        assert_valid_signature(&key.public_key, &message.try_to_vec().unwrap(), &signature);
+
+        key.next_nonce += 1;
+        self.permit_keys.insert(&owner_id, &key);
+        self.internal_set_allowance(
+            owner_id,
+            spender_id,
+            Allowance { amount: amount.0, expires_at: None },
+        );
+    }
+
+    /// Returns the nonce the next permit of `owner_id` must use.
+    pub fn permit_nonce_of(&self, owner_id: AccountId) -> U64 {
+        U64(self.permit_keys.get(&owner_id).map_or(0, |key| key.next_nonce))
+    }
+}
+
+/// Panics unless `signature` is a valid ed25519 signature of `message` by `public_key`.
+pub(crate) fn assert_valid_signature(
+    public_key: &PublicKey,
+    message: &[u8],
+    signature: &Base64VecU8,
+) {
+    let public_key = ed25519_dalek::PublicKey::from_bytes(&public_key.as_bytes()[1..])
+        .unwrap_or_else(|_| env::panic_str("Invalid permit key"));
+    let signature = ed25519_dalek::Signature::from_bytes(&signature.0)
+        .unwrap_or_else(|_| env::panic_str("Invalid signature"));
+    require!(public_key.verify(message, &signature).is_ok(), "Invalid signature");
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use std::convert::TryInto;
+
+    use ed25519_dalek::{ExpandedSecretKey, SecretKey};
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    const DEADLINE: u64 = 1_000;
+
+    /// Registers a permit key for accounts(2) and returns the key's secret.
+    fn setup() -> (VMContextBuilder, Contract, SecretKey) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let secret = SecretKey::from_bytes(&[7; 32]).unwrap();
+        let mut public_key = vec![CurveType::ED25519 as u8];
+        public_key.extend(ed25519_dalek::PublicKey::from(&secret).as_bytes());
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.set_permit_key(public_key.try_into().unwrap());
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .build());
+        (context, contract, secret)
+    }
+
+    fn sign(secret: &SecretKey, amount: Balance, nonce: u64) -> Base64VecU8 {
+        let message = PermitMessage {
+            domain: PERMIT_DOMAIN.to_string(),
+            contract_id: accounts(0),
+            owner_id: accounts(2),
+            spender_id: accounts(3),
+            amount,
+            deadline: DEADLINE,
+            nonce,
+        };
+        let public_key = ed25519_dalek::PublicKey::from(secret);
+        let signature =
+            ExpandedSecretKey::from(secret).sign(&message.try_to_vec().unwrap(), &public_key);
+        signature.to_bytes().to_vec().into()
+    }
+
+    #[test]
+    fn test_permit() {
+        let (_, mut contract, secret) = setup();
+        let signature = sign(&secret, 300, 0);
+        contract.permit(accounts(2), accounts(3), 300.into(), DEADLINE.into(), 0.into(), signature);
+        assert_eq!(contract.ft_allowance(accounts(2), accounts(3)).0, 300);
+        assert_eq!(contract.permit_nonce_of(accounts(2)).0, 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid permit nonce")]
+    fn test_permit_replay() {
+        let (_, mut contract, secret) = setup();
+        let signature = sign(&secret, 300, 0);
+        let deadline = U64(DEADLINE);
+        contract.permit(
+            accounts(2),
+            accounts(3),
+            300.into(),
+            deadline,
+            0.into(),
+            signature.clone(),
+        );
+        contract.permit(accounts(2), accounts(3), 300.into(), deadline, 0.into(), signature);
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid signature")]
+    fn test_permit_with_altered_amount() {
+        let (_, mut contract, secret) = setup();
+        let signature = sign(&secret, 300, 0);
+        contract.permit(
+            accounts(2),
+            accounts(3),
+            3000.into(),
+            DEADLINE.into(),
+            0.into(),
+            signature,
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Requires attached deposit of at least 1 yoctoNEAR")]
+    fn test_replace_permit_key_without_deposit() {
+        let (mut context, mut contract, _) = setup();
+        let mut public_key = vec![CurveType::ED25519 as u8];
+        public_key.extend([9; 32]);
+        testing_env!(context.attached_deposit(0).predecessor_account_id(accounts(2)).build());
+        contract.set_permit_key(public_key.try_into().unwrap());
+    }
+}
@@ -0,0 +1,287 @@
+//! Soft transfer limits with approval escalation.
+//!
+//! An account can set a soft limit and name an approver. Transfers up to the limit go through
+//! as usual, while larger `ft_transfer`s are queued until the approver signs off on them. The
+//! tokens stay in the owner's balance until the queued transfer is executed, so a queued transfer
+//! fails on approval if the owner no longer has enough balance. The owner's attached deposit pays
+//! for storing a queued transfer, and the storage is refunded to the owner once the transfer is
+//! approved or cancelled.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance, StorageUsage};
+
+use crate::events::FtTransferQueued;
+use crate::pause::Feature;
+use crate::utils::{assert_stored_memo, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of transfers an account can have waiting for approval at once.
+const MAX_QUEUED_TRANSFERS: usize = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferPolicy {
+    /// Transfers above this amount need the approver's sign-off.
+    pub soft_limit: U128,
+    /// Account that approves transfers above the soft limit and can remove the policy.
+    pub approver_id: AccountId,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct QueuedTransfer {
+    pub id: u64,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    pub memo: Option<String>,
+    /// Block timestamp (in nanoseconds) when the transfer was queued.
+    pub created_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Sets the caller's soft limit and approver. The attached deposit pays for storing the policy.
+    /// Once set, only the approver can remove the policy, so a compromised key can't lift it.
+    #[payable]
+    pub fn set_transfer_policy(&mut self, soft_limit: U128, approver_id: AccountId) {
+        let account_id = env::predecessor_account_id();
+        require!(
+            !self.transfer_policies.contains_key(&account_id),
+            "The transfer policy can only be removed by its approver"
+        );
+        require!(approver_id != account_id, "The approver should be a different account");
+        let initial_storage_usage = env::storage_usage();
+        self.transfer_policies.insert(&account_id, &TransferPolicy { soft_limit, approver_id });
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    /// Removes the policy of `account_id` and drops the transfers still waiting in its queue. The
+    /// storage is refunded to the account.
+    #[payable]
+    pub fn remove_transfer_policy(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        let policy = self.internal_unwrap_transfer_policy(&account_id);
+        require!(
+            env::predecessor_account_id() == policy.approver_id,
+            "Only the approver can remove the transfer policy"
+        );
+        let initial_storage_usage = env::storage_usage();
+        self.transfer_policies.remove(&account_id);
+        self.queued_transfers.remove(&account_id);
+        refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+    }
+
+    /// Executes the queued transfer `transfer_id` of `account_id`. Can only be called by the
+    /// approver named in the account's policy. The storage of the queued transfer is refunded to
+    /// the account.
+    #[payable]
+    pub fn approve_queued_transfer(&mut self, account_id: AccountId, transfer_id: u64) {
+        require!(env::attached_deposit() >= 1, "Requires attached deposit of at least 1 yoctoNEAR");
+        let policy = self.internal_unwrap_transfer_policy(&account_id);
+        require!(
+            env::predecessor_account_id() == policy.approver_id,
+            "Only the approver can approve queued transfers"
+        );
+        let transfer = self.internal_take_queued_transfer(&account_id, transfer_id);
+        refund_deposit(self.internal_transfer(
+            &account_id,
+            &transfer.receiver_id,
+            transfer.amount.into(),
+            transfer.memo,
+        ));
+    }
+
+    /// Drops the queued transfer `transfer_id` of `account_id`. Can be called by the account
+    /// itself or by its approver. The storage is refunded to the account.
+    #[payable]
+    pub fn cancel_queued_transfer(&mut self, account_id: AccountId, transfer_id: u64) {
+        assert_one_yocto();
+        let predecessor_account_id = env::predecessor_account_id();
+        let is_approver = self
+            .transfer_policies
+            .get(&account_id)
+            .map_or(false, |policy| policy.approver_id == predecessor_account_id);
+        require!(
+            predecessor_account_id == account_id || is_approver,
+            "Only the account or its approver can cancel queued transfers"
+        );
+        self.internal_take_queued_transfer(&account_id, transfer_id);
+    }
+
+    pub fn transfer_policy_of(&self, account_id: AccountId) -> Option<TransferPolicy> {
+        self.transfer_policies.get(&account_id)
+    }
+
+    pub fn queued_transfers_of(&self, account_id: AccountId) -> Vec<QueuedTransfer> {
+        self.queued_transfers.get(&account_id).unwrap_or_default()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_exceeds_soft_limit(
+        &self,
+        account_id: &AccountId,
+        amount: Balance,
+    ) -> bool {
+        self.transfer_policies.get(account_id).map_or(false, |policy| amount > policy.soft_limit.0)
+    }
+
+    /// Queues the transfer for the approver of `account_id`. Returns the storage taken by the
+    /// queued transfer, which the caller charges to the account.
+    pub(crate) fn internal_queue_transfer(
+        &mut self,
+        account_id: AccountId,
+        receiver_id: AccountId,
+        amount: Balance,
+        memo: Option<String>,
+    ) -> StorageUsage {
+        self.assert_not_paused(Feature::Transfer);
+        require!(account_id != receiver_id, "Sender and receiver should be different");
+        require!(amount > 0, "The amount should be a positive number");
+        require!(
+            self.token.internal_unwrap_balance_of(&account_id) >= amount,
+            "The account doesn't have enough balance"
+        );
+        let policy = self.internal_unwrap_transfer_policy(&account_id);
+        let mut queue = self.queued_transfers.get(&account_id).unwrap_or_default();
+        require!(queue.len() < MAX_QUEUED_TRANSFERS, "Too many transfers are waiting for approval");
+        assert_stored_memo(&memo);
+
+        let id = self.next_queued_transfer_id;
+        self.next_queued_transfer_id += 1;
+        FtTransferQueued {
+            transfer_id: id,
+            owner_id: &account_id,
+            receiver_id: &receiver_id,
+            amount: &U128(amount),
+            approver_id: &policy.approver_id,
+        }
+        .emit();
+        queue.push(QueuedTransfer {
+            id,
+            receiver_id,
+            amount: U128(amount),
+            memo,
+            created_at: env::block_timestamp().into(),
+        });
+        let initial_storage_usage = env::storage_usage();
+        self.queued_transfers.insert(&account_id, &queue);
+        env::storage_usage() - initial_storage_usage
+    }
+
+    fn internal_take_queued_transfer(
+        &mut self,
+        account_id: &AccountId,
+        transfer_id: u64,
+    ) -> QueuedTransfer {
+        let mut queue = self.queued_transfers.get(account_id).unwrap_or_default();
+        let index = queue
+            .iter()
+            .position(|transfer| transfer.id == transfer_id)
+            .unwrap_or_else(|| env::panic_str("The queued transfer doesn't exist"));
+        let transfer = queue.remove(index);
+        let initial_storage_usage = env::storage_usage();
+        if queue.is_empty() {
+            self.queued_transfers.remove(account_id);
+        } else {
+            self.queued_transfers.insert(account_id, &queue);
+        }
+        refund_released_storage(account_id.clone(), initial_storage_usage - env::storage_usage());
+        transfer
+    }
+
+    fn internal_unwrap_transfer_policy(&self, account_id: &AccountId) -> TransferPolicy {
+        self.transfer_policies
+            .get(account_id)
+            .unwrap_or_else(|| env::panic_str("The account has no transfer policy"))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{last_refund_to, setup_contract, with_storage_deposit};
+
+    fn setup() -> (near_sdk::test_utils::VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.set_transfer_policy(100.into(), accounts(3));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_transfer_below_soft_limit() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert!(contract.queued_transfers_of(accounts(2)).is_empty());
+    }
+
+    #[test]
+    fn test_transfer_above_soft_limit_is_queued() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.ft_transfer(accounts(1), 500.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        let queue = contract.queued_transfers_of(accounts(2));
+        assert_eq!(queue.len(), 1);
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(3))
+            .build());
+        contract.approve_queued_transfer(accounts(2), queue[0].id);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 500);
+        assert!(contract.queued_transfers_of(accounts(2)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the approver can approve queued transfers")]
+    fn test_owner_cannot_approve_own_transfer() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.ft_transfer(accounts(1), 500.into(), None);
+        let id = contract.queued_transfers_of(accounts(2))[0].id;
+        contract.approve_queued_transfer(accounts(2), id);
+    }
+
+    #[test]
+    #[should_panic(expected = "Must attach")]
+    fn test_queue_without_storage_deposit() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 500.into(), None);
+    }
+
+    #[test]
+    fn test_cancel_refunds_storage() {
+        let (mut context, mut contract) = setup();
+        let deposit = env::storage_byte_cost() * 1000;
+        testing_env!(context.attached_deposit(deposit).build());
+        contract.ft_transfer(accounts(1), 500.into(), None);
+        let unused = last_refund_to(accounts(2));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        let id = contract.queued_transfers_of(accounts(2))[0].id;
+        contract.cancel_queued_transfer(accounts(2), id);
+        assert_eq!(last_refund_to(accounts(2)), deposit - unused);
+    }
+
+    #[test]
+    #[should_panic(expected = "The transfer feature is paused")]
+    fn test_queue_while_paused() {
+        let (mut context, mut contract) = setup();
+        contract.pause();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.ft_transfer_or_park(accounts(1), 500.into(), None);
+    }
+}
@@ -0,0 +1,115 @@
+//! Raw Borsh state views for light clients and state-proof verifiers.
+//!
+//! The storage layout of the records returned here is part of the contract's interface:
+//! - An account balance is stored under the key `TOKEN_PREFIX ++ borsh(account_id)`, i.e. `b"a"`
+//!   followed by the little-endian `u32` length of the account ID and its bytes. The value is the
+//!   balance as a little-endian `u128`.
+//! - The token metadata is stored under the key `METADATA_PREFIX` (`b"m"`). The value is the
+//!   Borsh-encoded `FungibleTokenMetadata`.
+//!
+//! Keys are not hashed, so a verifier can compute the key of any account and check the returned
+//! value against a state proof of the contract account. State-sync tooling that already has the
+//! keys can read many balance records at once with `ft_account_raw_records`.
+
+use near_sdk::borsh::BorshSerialize;
+use near_sdk::json_types::Base64VecU8;
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::types::RawStateRecord;
+use crate::{Contract, ContractExt};
+
+/// Prefix of the account balances map.
+pub(crate) const TOKEN_PREFIX: &[u8] = b"a";
+/// Key of the token metadata.
+pub(crate) const METADATA_PREFIX: &[u8] = b"m";
+
+/// Maximum number of records returned by a single bulk read.
+const MAX_RAW_RECORDS: usize = 100;
+
+#[near_bindgen]
+impl Contract {
+    /// Returns the storage key and the raw balance record of `account_id`. The value is `None`
+    /// if the account is not registered.
+    pub fn ft_account_raw_record(&self, account_id: AccountId) -> RawStateRecord {
+        raw_record([TOKEN_PREFIX, &account_id.try_to_vec().unwrap()].concat())
+    }
+
+    /// Returns the raw balance records stored under the given storage keys, in the same order.
+    /// Every key must be a balance key, i.e. start with `TOKEN_PREFIX`.
+    pub fn ft_account_raw_records(&self, keys: Vec<Base64VecU8>) -> Vec<RawStateRecord> {
+        require!(keys.len() <= MAX_RAW_RECORDS, "Too many keys");
+        keys.into_iter()
+            .map(|key| {
+                require!(key.0.starts_with(TOKEN_PREFIX), "Not a balance key");
+                raw_record(key.0)
+            })
+            .collect()
+    }
+
+    /// Returns the storage key and the raw Borsh-encoded token metadata.
+    pub fn ft_metadata_raw_record(&self) -> RawStateRecord {
+        raw_record(METADATA_PREFIX.to_vec())
+    }
+}
+
+fn raw_record(key: Vec<u8>) -> RawStateRecord {
+    let value = env::storage_read(&key).map(Base64VecU8);
+    RawStateRecord { key: Base64VecU8(key), value }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::metadata::{
+        FungibleTokenMetadata, FungibleTokenMetadataProvider,
+    };
+    use near_sdk::borsh::BorshDeserialize;
+    use near_sdk::test_utils::accounts;
+    use near_sdk::{testing_env, Balance};
+
+    use super::*;
+    use crate::tests::{get_context, TOTAL_SUPPLY};
+
+    #[test]
+    fn test_account_raw_record() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
+
+        let record = contract.ft_account_raw_record(accounts(1));
+        let mut expected_key = b"a".to_vec();
+        expected_key.extend((accounts(1).as_str().len() as u32).to_le_bytes());
+        expected_key.extend(accounts(1).as_bytes());
+        assert_eq!(record.key.0, expected_key);
+        let balance = Balance::try_from_slice(&record.value.unwrap().0).unwrap();
+        assert_eq!(balance, TOTAL_SUPPLY);
+
+        assert!(contract.ft_account_raw_record(accounts(3)).value.is_none());
+    }
+
+    #[test]
+    fn test_account_raw_records() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
+
+        let keys = vec![
+            contract.ft_account_raw_record(accounts(1)).key,
+            contract.ft_account_raw_record(accounts(3)).key,
+        ];
+        let records = contract.ft_account_raw_records(keys);
+        assert_eq!(records.len(), 2);
+        assert!(records[0].value.is_some());
+        assert!(records[1].value.is_none());
+    }
+
+    #[test]
+    fn test_metadata_raw_record() {
+        let context = get_context(accounts(1));
+        testing_env!(context.build());
+        let contract = Contract::new_default_meta(accounts(1), TOTAL_SUPPLY.into());
+
+        let record = contract.ft_metadata_raw_record();
+        let metadata = FungibleTokenMetadata::try_from_slice(&record.value.unwrap().0).unwrap();
+        assert_eq!(metadata.symbol, contract.ft_metadata().symbol);
+    }
+}
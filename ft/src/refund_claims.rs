@@ -0,0 +1,186 @@
+//! Refunds of transfer calls to senders that unregistered while the call was in flight.
+//!
+//! Instead of burning such a refund, `ft_resolve_transfer` moves it to the contract's own balance
+//! and records a claim for the sender. Once registered again, the sender can get the tokens back
+//! with `claim_refund` until the claim expires, after which the owner can move them to the
+//! recovery account with `reclaim_expired_refund`. Refunds parked again for a sender with an open
+//! claim are added to it without extending its expiry. Storing the claims is paid by the
+//! contract.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance};
+
+use crate::{Contract, ContractExt};
+
+/// How long a refund can be claimed, in nanoseconds.
+const REFUND_CLAIM_PERIOD: u64 = 30 * 24 * 60 * 60 * 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RefundClaim {
+    pub amount: U128,
+    /// Block timestamp (in nanoseconds) after which the refund can no longer be claimed.
+    pub expires_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Credits the refunds parked for the caller, who must be registered again.
+    #[payable]
+    pub fn claim_refund(&mut self) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        let claim = self
+            .refund_claims
+            .remove(&account_id)
+            .unwrap_or_else(|| env::panic_str("No refund to claim"));
+        require!(env::block_timestamp() < claim.expires_at.0, "The refund claim has expired");
+        self.internal_release(&account_id, claim.amount.0, "refund claim");
+    }
+
+    /// Moves the refund parked for `account_id` to the recovery account once its claim has
+    /// expired.
+    #[payable]
+    pub fn reclaim_expired_refund(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        self.assert_owner();
+        let claim = self
+            .refund_claims
+            .remove(&account_id)
+            .unwrap_or_else(|| env::panic_str("No refund to claim"));
+        require!(
+            env::block_timestamp() >= claim.expires_at.0,
+            "The refund claim has not expired yet"
+        );
+        let recovery_id = self.refund_recovery();
+        self.internal_release(&recovery_id, claim.amount.0, "expired refund claim");
+    }
+
+    /// Sets the account receiving the expired refund claims, or the owner when `None`.
+    #[payable]
+    pub fn set_refund_recovery(&mut self, recovery_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(recovery_id) = recovery_id.as_ref() {
+            self.token.internal_unwrap_balance_of(recovery_id);
+        }
+        self.refund_recovery_id = recovery_id;
+    }
+
+    pub fn refund_recovery(&self) -> AccountId {
+        self.refund_recovery_id.clone().unwrap_or_else(|| self.owner_id.clone())
+    }
+
+    pub fn refund_claim_of(&self, account_id: AccountId) -> Option<RefundClaim> {
+        self.refund_claims.get(&account_id)
+    }
+}
+
+impl Contract {
+    /// Records `amount`, already moved to the contract's balance, as claimable by `account_id`.
+    pub(crate) fn internal_park_refund(&mut self, account_id: AccountId, amount: Balance) {
+        if amount == 0 {
+            return;
+        }
+        let claim = match self.refund_claims.get(&account_id) {
+            Some(claim) => RefundClaim { amount: U128(claim.amount.0 + amount), ..claim },
+            None => RefundClaim {
+                amount: U128(amount),
+                expires_at: U64(env::block_timestamp() + REFUND_CLAIM_PERIOD),
+            },
+        };
+        self.refund_claims.insert(&account_id, &claim);
+        log!("Parked a refund of {} for @{}", amount, account_id);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::fungible_token::resolver::FungibleTokenResolver;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::{testing_env, PromiseResult, RuntimeFeesConfig, VMConfig};
+
+    use super::*;
+    use crate::tests::{register, setup_contract, TOTAL_SUPPLY};
+
+    /// accounts(1) transfer-calls its 100 tokens to accounts(2), unregisters, and the call
+    /// fails.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.ft_transfer_call(accounts(2), 100.into(), None, String::new());
+        contract.storage_unregister(None);
+
+        testing_env!(
+            context.predecessor_account_id(accounts(0)).build(),
+            VMConfig::test(),
+            RuntimeFeesConfig::test(),
+            Default::default(),
+            vec![PromiseResult::Failed],
+        );
+        let used = contract.ft_resolve_transfer(accounts(1), accounts(2), 100.into());
+        assert_eq!(used.0, 0);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_claim_refund_after_registering_again() {
+        let (mut context, mut contract) = setup();
+        register(&mut context, &mut contract, accounts(1));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.claim_refund();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+        assert!(contract.refund_claim_of(accounts(1)).is_none());
+    }
+
+    #[test]
+    fn test_reclaim_expired_refund() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .block_timestamp(REFUND_CLAIM_PERIOD)
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.reclaim_expired_refund(accounts(1));
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_reclaim_expired_refund_by_non_owner() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(REFUND_CLAIM_PERIOD).attached_deposit(1).build());
+        contract.reclaim_expired_refund(accounts(1));
+    }
+
+    #[test]
+    fn test_parking_again_keeps_expiry() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(1_000).build());
+        contract.internal_park_refund(accounts(1), 50);
+        let claim = contract.refund_claim_of(accounts(1)).unwrap();
+        assert_eq!(claim.amount.0, 150);
+        assert_eq!(claim.expires_at.0, REFUND_CLAIM_PERIOD);
+    }
+
+    #[test]
+    fn test_reclaim_expired_refund_to_recovery_account() {
+        let (mut context, mut contract) = setup();
+        register(&mut context, &mut contract, accounts(3));
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(2)).build());
+        contract.set_refund_recovery(Some(accounts(3)));
+        testing_env!(context.block_timestamp(REFUND_CLAIM_PERIOD).build());
+        contract.reclaim_expired_refund(accounts(1));
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 0);
+    }
+}
@@ -0,0 +1,193 @@
+//! Deterministic replay of recorded receipts, for reproducing reported balance discrepancies.
+//!
+//! Only available with the `replay` feature and outside of wasm. `replay` takes the function
+//! call receipts of the contract as JSON, in execution order, and applies them to a fresh state
+//! in the mocked blockchain. The first receipt must be the `new` call. Only receipts that
+//! succeeded on chain should be included, since a panicking call is not reverted here.
+//!
+//! Supported methods: `new`, `storage_deposit`, `storage_unregister`, `ft_transfer`, `ft_mint`
+//! and `ft_burn`.
+
+use near_contract_standards::fungible_token::core::FungibleTokenCore;
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_contract_standards::storage_management::StorageManagement;
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::de::DeserializeOwned;
+use near_sdk::serde::Deserialize;
+use near_sdk::serde_json::{self, Value};
+use near_sdk::test_utils::VMContextBuilder;
+use near_sdk::{env, AccountId, MockedBlockchain, RuntimeFeesConfig, VMConfig};
+
+use crate::Contract;
+
+/// A function call receipt of the contract, as recorded on chain.
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+pub struct RecordedReceipt {
+    pub predecessor_id: AccountId,
+    pub method_name: String,
+    #[serde(default)]
+    pub args: Value,
+    pub attached_deposit: Option<U128>,
+    pub block_timestamp: Option<U64>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct NewArgs {
+    owner_id: AccountId,
+    total_supply: U128,
+    metadata: FungibleTokenMetadata,
+    max_supply: Option<U128>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct StorageDepositArgs {
+    account_id: Option<AccountId>,
+    registration_only: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct StorageUnregisterArgs {
+    force: Option<bool>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtTransferArgs {
+    receiver_id: AccountId,
+    amount: U128,
+    memo: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtMintArgs {
+    account_id: AccountId,
+    amount: U128,
+    memo: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(crate = "near_sdk::serde")]
+struct FtBurnArgs {
+    amount: U128,
+    memo: Option<String>,
+}
+
+/// Replays `receipts`, a JSON array of `RecordedReceipt`, against a fresh contract deployed at
+/// `contract_id` and returns the resulting contract.
+pub fn replay(contract_id: AccountId, receipts: &str) -> Contract {
+    let receipts: Vec<RecordedReceipt> =
+        serde_json::from_str(receipts).unwrap_or_else(|e| panic!("Invalid receipts: {}", e));
+    let mut receipts = receipts.into_iter();
+    let init = receipts.next().expect("No receipts to replay");
+    assert_eq!(init.method_name, "new", "The first receipt must initialize the contract");
+
+    env::set_blockchain_interface(MockedBlockchain::new(
+        context(&contract_id, &init, 0).build(),
+        VMConfig::test(),
+        RuntimeFeesConfig::test(),
+        vec![],
+        Default::default(),
+        Default::default(),
+        None,
+    ));
+    let args: NewArgs = parse_args(init.args);
+    let mut contract =
+        Contract::new(args.owner_id, args.total_supply, args.metadata, args.max_supply);
+
+    for receipt in receipts {
+        near_sdk::testing_env!(context(&contract_id, &receipt, env::storage_usage()).build());
+        match receipt.method_name.as_str() {
+            "storage_deposit" => {
+                let args: StorageDepositArgs = parse_args(receipt.args);
+                contract.storage_deposit(args.account_id, args.registration_only);
+            }
+            "storage_unregister" => {
+                let args: StorageUnregisterArgs = parse_args(receipt.args);
+                contract.storage_unregister(args.force);
+            }
+            "ft_transfer" => {
+                let args: FtTransferArgs = parse_args(receipt.args);
+                contract.ft_transfer(args.receiver_id, args.amount, args.memo);
+            }
+            "ft_mint" => {
+                let args: FtMintArgs = parse_args(receipt.args);
+                contract.ft_mint(args.account_id, args.amount, args.memo);
+            }
+            "ft_burn" => {
+                let args: FtBurnArgs = parse_args(receipt.args);
+                contract.ft_burn(args.amount, args.memo);
+            }
+            method_name => panic!("Replaying {} is not supported", method_name),
+        }
+    }
+    contract
+}
+
+fn context(
+    contract_id: &AccountId,
+    receipt: &RecordedReceipt,
+    storage_usage: u64,
+) -> VMContextBuilder {
+    let mut builder = VMContextBuilder::new();
+    builder
+        .current_account_id(contract_id.clone())
+        .signer_account_id(receipt.predecessor_id.clone())
+        .predecessor_account_id(receipt.predecessor_id.clone())
+        .attached_deposit(receipt.attached_deposit.map_or(0, |deposit| deposit.0))
+        .block_timestamp(receipt.block_timestamp.map_or(0, |timestamp| timestamp.0))
+        .storage_usage(storage_usage);
+    builder
+}
+
+fn parse_args<T: DeserializeOwned>(args: Value) -> T {
+    serde_json::from_value(args).unwrap_or_else(|e| panic!("Invalid arguments: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use near_sdk::test_utils::accounts;
+
+    use super::*;
+
+    #[test]
+    fn test_replay() {
+        let receipts = r#"[
+            {
+                "predecessor_id": "alice",
+                "method_name": "new",
+                "args": {
+                    "owner_id": "alice",
+                    "total_supply": "1000",
+                    "metadata": { "spec": "ft-1.0.0", "name": "Token", "symbol": "TKN", "decimals": 8 }
+                }
+            },
+            {
+                "predecessor_id": "bob",
+                "method_name": "storage_deposit",
+                "args": {},
+                "attached_deposit": "10000000000000000000000"
+            },
+            {
+                "predecessor_id": "alice",
+                "method_name": "ft_transfer",
+                "args": { "receiver_id": "bob", "amount": "300" },
+                "attached_deposit": "1"
+            },
+            {
+                "predecessor_id": "bob",
+                "method_name": "ft_burn",
+                "args": { "amount": "100" },
+                "attached_deposit": "1"
+            }
+        ]"#;
+        let contract = replay(accounts(5), receipts);
+        assert_eq!(contract.ft_balance_of(accounts(0)).0, 700);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 200);
+        assert_eq!(contract.ft_total_supply().0, 900);
+    }
+}
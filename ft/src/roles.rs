@@ -0,0 +1,162 @@
+//! Role-based access control.
+//!
+//! Privileged features are gated by roles rather than by the owner alone, so the account that
+//! mints can be kept apart from the one that handles incidents. Only the owner grants and revokes
+//! roles; the initial owner starts with every role and can hand them out or drop them.
+//!
+//! Methods that need a role check it with the [`require_role!`] guard.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::{FtRoleGranted, FtRoleRevoked};
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde", rename_all = "snake_case")]
+pub enum Role {
+    Minter,
+    Pauser,
+    Compliance,
+    Arbiter,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Minter, Role::Pauser, Role::Compliance, Role::Arbiter];
+
+    fn bit(self) -> u8 {
+        1 << self as u8
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Role::Minter => "minter",
+            Role::Pauser => "pauser",
+            Role::Compliance => "compliance",
+            Role::Arbiter => "arbiter",
+        }
+    }
+}
+
+/// The roles held by an account, stored as a bitmask.
+#[derive(BorshDeserialize, BorshSerialize, Clone, Copy, Default)]
+pub struct RoleSet(u8);
+
+impl RoleSet {
+    pub fn all() -> Self {
+        Role::ALL.iter().fold(Self::default(), |set, &role| set.with(role))
+    }
+
+    pub fn contains(self, role: Role) -> bool {
+        self.0 & role.bit() != 0
+    }
+
+    pub fn with(self, role: Role) -> Self {
+        Self(self.0 | role.bit())
+    }
+
+    pub fn without(self, role: Role) -> Self {
+        Self(self.0 & !role.bit())
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+}
+
+/// Panics unless the caller holds `$role`.
+macro_rules! require_role {
+    ($contract:expr, $role:expr) => {
+        $contract.internal_assert_role(&near_sdk::env::predecessor_account_id(), $role)
+    };
+}
+pub(crate) use require_role;
+
+#[near_bindgen]
+impl Contract {
+    /// Grants `role` to `account_id`. Can only be called by the owner, whose attached deposit
+    /// pays for storing the role.
+    #[payable]
+    pub fn grant_role(&mut self, account_id: AccountId, role: Role) {
+        self.assert_owner();
+        let roles = self.roles.get(&account_id).unwrap_or_default();
+        require!(!roles.contains(role), "The account already has the role");
+        let initial_storage_usage = env::storage_usage();
+        self.roles.insert(&account_id, &roles.with(role));
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        FtRoleGranted { account_id: &account_id, role }.emit();
+    }
+
+    /// Revokes `role` from `account_id`. Can only be called by the owner.
+    #[payable]
+    pub fn revoke_role(&mut self, account_id: AccountId, role: Role) {
+        assert_one_yocto();
+        self.assert_owner();
+        let roles = self.roles.get(&account_id).unwrap_or_default();
+        require!(roles.contains(role), "The account doesn't have the role");
+        let initial_storage_usage = env::storage_usage();
+        let roles = roles.without(role);
+        if roles.is_empty() {
+            self.roles.remove(&account_id);
+        } else {
+            self.roles.insert(&account_id, &roles);
+        }
+        refund_released_storage(
+            env::predecessor_account_id(),
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+        FtRoleRevoked { account_id: &account_id, role }.emit();
+    }
+
+    pub fn has_role(&self, account_id: AccountId, role: Role) -> bool {
+        self.roles.get(&account_id).map_or(false, |roles| roles.contains(role))
+    }
+
+    pub fn roles_of(&self, account_id: AccountId) -> Vec<Role> {
+        let roles = self.roles.get(&account_id).unwrap_or_default();
+        Role::ALL.iter().copied().filter(|&role| roles.contains(role)).collect()
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_assert_role(&self, account_id: &AccountId, role: Role) {
+        if !self.roles.get(account_id).map_or(false, |roles| roles.contains(role)) {
+            env::panic_str(&format!("The caller doesn't have the {} role", role.name()));
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    #[test]
+    fn test_grant_and_revoke_role() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        assert_eq!(contract.roles_of(accounts(2)), Role::ALL.to_vec());
+
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.grant_role(accounts(1), Role::Pauser);
+        assert!(contract.has_role(accounts(1), Role::Pauser));
+        assert!(!contract.has_role(accounts(1), Role::Minter));
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.revoke_role(accounts(1), Role::Pauser);
+        assert!(contract.roles_of(accounts(1)).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the owner can call this method")]
+    fn test_grant_role_by_non_owner() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.grant_role(accounts(1), Role::Minter);
+    }
+}
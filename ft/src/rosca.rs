@@ -0,0 +1,343 @@
+//! Rotating savings and credit associations (ROSCAs).
+//!
+//! A fixed group of members each contributes the same amount every period, and each period the
+//! whole pot goes to one member who hasn't received it yet, either in the order of the members or
+//! drawn by lottery. The group runs for as many periods as it has members.
+//!
+//! Anyone can close a period once it has ended. Members who didn't contribute during the period
+//! are marked as defaulted and lose their turn to receive the pot; what they had already
+//! contributed stays with the group, and they can't contribute anymore. If no member can receive
+//! the pot, the period's contributions are returned to their contributors. Payouts to members who
+//! have unregistered, are blacklisted or are not whitelisted are parked as refund claims (see
+//! `refund_claims`). The creator pays for storing the group and gets the storage refunded when the
+//! last period is closed.
+//!
+//! Members don't post collateral, so a member who stops contributing after receiving the pot
+//! keeps it, and the remaining members bear the loss. Groups are meant for members who trust each
+//! other, and the creator should order the members (or avoid the lottery) accordingly.
+
+use std::convert::TryInto;
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::events::FtRoscaRoundClosed;
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+const MIN_MEMBERS: usize = 2;
+const MAX_MEMBERS: usize = 20;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RoscaMember {
+    pub account_id: AccountId,
+    /// Whether the member contributed during the current period.
+    pub contributed: bool,
+    pub received: bool,
+    pub defaulted: bool,
+}
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Rosca {
+    pub group_id: u64,
+    pub creator_id: AccountId,
+    pub members: Vec<RoscaMember>,
+    /// Amount each member contributes per period.
+    pub contribution: U128,
+    /// Start of the first period and length of each period, in nanoseconds.
+    pub start: U64,
+    pub period: U64,
+    /// Whether the recipient of each pot is drawn by lottery rather than in the members' order.
+    pub lottery: bool,
+    /// Index of the current period.
+    pub round: u32,
+    /// Contributions collected during the current period.
+    pub pot: U128,
+}
+
+impl Rosca {
+    fn round_end(&self) -> u64 {
+        self.start.0 + (u64::from(self.round) + 1) * self.period.0
+    }
+
+    /// Returns the index of the member receiving the current pot, if any member can.
+    fn draw_recipient(&self) -> Option<usize> {
+        let eligible: Vec<usize> = (0..self.members.len())
+            .filter(|&index| !self.members[index].received && !self.members[index].defaulted)
+            .collect();
+        if eligible.is_empty() {
+            return None;
+        }
+        if !self.lottery {
+            return Some(eligible[0]);
+        }
+        let seed = u64::from_le_bytes(env::random_seed()[..8].try_into().unwrap());
+        Some(eligible[(seed % eligible.len() as u64) as usize])
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates a group in which each of `members` contributes `contribution` every `period`,
+    /// starting at `start`, and returns its id. The attached deposit pays for storing the group.
+    #[payable]
+    pub fn create_rosca(
+        &mut self,
+        members: Vec<AccountId>,
+        contribution: U128,
+        start: U64,
+        period: U64,
+        lottery: bool,
+    ) -> u64 {
+        require!(
+            (MIN_MEMBERS..=MAX_MEMBERS).contains(&members.len()),
+            format!("A group has {} to {} members", MIN_MEMBERS, MAX_MEMBERS)
+        );
+        require!(contribution.0 > 0, "The contribution should be a positive number");
+        require!(period.0 > 0, "The period should be a positive number");
+        require!(start.0 >= env::block_timestamp(), "The group can't start in the past");
+        for (index, account_id) in members.iter().enumerate() {
+            require!(!members[..index].contains(account_id), "Duplicate group member");
+            self.token.internal_unwrap_balance_of(account_id);
+        }
+
+        let initial_storage_usage = env::storage_usage();
+        let group_id = self.next_rosca_id;
+        self.next_rosca_id += 1;
+        let members = members
+            .into_iter()
+            .map(|account_id| RoscaMember {
+                account_id,
+                contributed: false,
+                received: false,
+                defaulted: false,
+            })
+            .collect();
+        self.roscas.insert(
+            &group_id,
+            &Rosca {
+                group_id,
+                creator_id: env::predecessor_account_id(),
+                members,
+                contribution,
+                start,
+                period,
+                lottery,
+                round: 0,
+                pot: U128(0),
+            },
+        );
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        group_id
+    }
+
+    /// Pays the caller's contribution to the current period of `group_id`.
+    #[payable]
+    pub fn contribute_to_rosca(&mut self, group_id: u64) {
+        assert_one_yocto();
+        let mut group = self.internal_unwrap_rosca(group_id);
+        let now = env::block_timestamp();
+        require!(now >= group.start.0, "The group hasn't started yet");
+        require!(now < group.round_end(), "The period is over, close it first");
+        let account_id = env::predecessor_account_id();
+        let member = group
+            .members
+            .iter_mut()
+            .find(|member| member.account_id == account_id)
+            .unwrap_or_else(|| env::panic_str("The account isn't a member of the group"));
+        require!(!member.defaulted, "The member has defaulted");
+        require!(!member.contributed, "Already contributed for this period");
+        member.contributed = true;
+        group.pot = U128(group.pot.0 + group.contribution.0);
+        let contribution = group.contribution.0;
+        self.roscas.insert(&group_id, &group);
+        self.internal_hold(&account_id, contribution, "rosca");
+    }
+
+    /// Closes the ended period of `group_id`: marks the members who didn't contribute as
+    /// defaulted and pays the pot to the next recipient.
+    #[payable]
+    pub fn close_rosca_round(&mut self, group_id: u64) {
+        assert_one_yocto();
+        let mut group = self.internal_unwrap_rosca(group_id);
+        require!(env::block_timestamp() >= group.round_end(), "The period hasn't ended yet");
+        for member in group.members.iter_mut().filter(|member| !member.contributed) {
+            member.defaulted = true;
+        }
+        let pot = group.pot.0;
+        let recipient = group.draw_recipient();
+        match recipient {
+            Some(index) => {
+                group.members[index].received = true;
+                if pot > 0 {
+                    let recipient_id = group.members[index].account_id.clone();
+                    self.internal_release_or_park(&recipient_id, pot);
+                }
+            }
+            None => {
+                let contribution = group.contribution.0;
+                for member in group.members.iter().filter(|member| member.contributed) {
+                    self.internal_release_or_park(&member.account_id, contribution);
+                }
+            }
+        }
+        FtRoscaRoundClosed {
+            group_id,
+            round: group.round,
+            recipient_id: recipient.map(|index| &group.members[index].account_id),
+            amount: &U128(pot),
+            defaulters: group
+                .members
+                .iter()
+                .filter(|member| !member.contributed)
+                .map(|member| &member.account_id)
+                .collect(),
+        }
+        .emit();
+
+        group.round += 1;
+        group.pot = U128(0);
+        for member in group.members.iter_mut() {
+            member.contributed = false;
+        }
+        if group.round as usize == group.members.len() {
+            let initial_storage_usage = env::storage_usage();
+            self.roscas.remove(&group_id);
+            refund_released_storage(
+                group.creator_id,
+                initial_storage_usage.saturating_sub(env::storage_usage()),
+            );
+        } else {
+            self.roscas.insert(&group_id, &group);
+        }
+    }
+
+    pub fn rosca(&self, group_id: u64) -> Option<Rosca> {
+        self.roscas.get(&group_id)
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_rosca(&self, group_id: u64) -> Rosca {
+        self.roscas.get(&group_id).unwrap_or_else(|| env::panic_str("The group doesn't exist"))
+    }
+
+    /// Pays out held tokens, parking them if the account has unregistered in the meantime or
+    /// can't receive tokens right now.
+    fn internal_release_or_park(&mut self, account_id: &AccountId, amount: u128) {
+        if self.token.accounts.contains_key(account_id)
+            && !self.blacklist.contains(account_id)
+            && self.internal_is_whitelisted(account_id)
+        {
+            self.internal_release(account_id, amount, "rosca");
+        } else {
+            self.internal_park_refund(account_id.clone(), amount);
+        }
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{register, setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const PERIOD: u64 = 1_000;
+
+    /// Gives accounts(1) and accounts(3) 1000 tokens each and makes a group of them and
+    /// accounts(2) contributing 100 per period.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[]);
+        for account_id in [accounts(1), accounts(3)] {
+            register(&mut context, &mut contract, account_id.clone());
+            contract.ft_transfer(account_id, 1000.into(), None);
+        }
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.create_rosca(
+            vec![accounts(1), accounts(2), accounts(3)],
+            100.into(),
+            0.into(),
+            PERIOD.into(),
+            false,
+        );
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    fn contribute(context: &mut VMContextBuilder, contract: &mut Contract, account_id: AccountId) {
+        testing_env!(context.predecessor_account_id(account_id).build());
+        contract.contribute_to_rosca(0);
+    }
+
+    #[test]
+    fn test_rotating_payout() {
+        let (mut context, mut contract) = setup();
+        for account_id in [accounts(1), accounts(2), accounts(3)] {
+            contribute(&mut context, &mut contract, account_id);
+        }
+        testing_env!(context.block_timestamp(PERIOD).build());
+        contract.close_rosca_round(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1200);
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 900);
+        let group = contract.rosca(0).unwrap();
+        assert_eq!(group.round, 1);
+        assert!(group.members[0].received);
+    }
+
+    #[test]
+    fn test_defaulter_loses_turn() {
+        let (mut context, mut contract) = setup();
+        contribute(&mut context, &mut contract, accounts(2));
+        contribute(&mut context, &mut contract, accounts(3));
+        testing_env!(context.block_timestamp(PERIOD).build());
+        contract.close_rosca_round(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 2000 + 100);
+        let group = contract.rosca(0).unwrap();
+        assert!(group.members[0].defaulted);
+        assert!(group.members[1].received);
+    }
+
+    #[test]
+    #[should_panic(expected = "The period is over, close it first")]
+    fn test_contribute_after_period() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(PERIOD).build());
+        contract.contribute_to_rosca(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The member has defaulted")]
+    fn test_defaulter_cant_contribute() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(PERIOD).build());
+        contract.close_rosca_round(0);
+        contribute(&mut context, &mut contract, accounts(1));
+    }
+
+    #[test]
+    fn test_payout_to_blacklisted_recipient_is_parked() {
+        let (mut context, mut contract) = setup();
+        for account_id in [accounts(1), accounts(2), accounts(3)] {
+            contribute(&mut context, &mut contract, account_id);
+        }
+        testing_env!(context
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .predecessor_account_id(accounts(2))
+            .build());
+        contract.add_to_blacklist(accounts(1));
+        testing_env!(context.attached_deposit(1).block_timestamp(PERIOD).build());
+        contract.close_rosca_round(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 900);
+        assert_eq!(contract.refund_claim_of(accounts(1)).unwrap().amount.0, 300);
+    }
+}
@@ -0,0 +1,237 @@
+//! Savings goals with self-imposed withdrawal friction.
+//!
+//! A holder can set aside tokens in named savings goals, held by the contract as tagged
+//! sub-balances. Each goal has a withdrawal delay, counted from `request_savings_withdrawal`,
+//! and optionally a penalty for withdrawing before the delay has passed, which goes to a charity
+//! account of the holder's choosing. Without a penalty, early withdrawals are refused. The holder
+//! pays for storing its goals and gets the storage refunded when closing them.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::FtSavingsWithdrawn;
+use crate::utils::{pro_rata, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of savings goals per account.
+const MAX_GOALS: usize = 10;
+const MAX_GOAL_NAME_LEN: usize = 32;
+const MAX_BPS: u16 = 10_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct SavingsGoal {
+    pub name: String,
+    pub balance: U128,
+    /// Time (in nanoseconds) between requesting a withdrawal and withdrawing without penalty.
+    pub withdrawal_delay: U64,
+    /// Block timestamp (in nanoseconds) from which the requested withdrawal is free of penalty,
+    /// zero if no withdrawal was requested.
+    pub withdrawal_unlocks_at: U64,
+    /// Share of an early withdrawal, in basis points, that goes to `charity_id`.
+    pub penalty_bps: u16,
+    pub charity_id: Option<AccountId>,
+}
+
+impl SavingsGoal {
+    fn is_unlocked(&self) -> bool {
+        let unlocks_at = self.withdrawal_unlocks_at.0;
+        self.withdrawal_delay.0 == 0 || (unlocks_at > 0 && env::block_timestamp() >= unlocks_at)
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Creates the caller's savings goal `name`. Early withdrawals are refused unless
+    /// `penalty_bps` and `charity_id` are set. The attached deposit pays for storing the goal.
+    #[payable]
+    pub fn create_savings_goal(
+        &mut self,
+        name: String,
+        withdrawal_delay: U64,
+        penalty_bps: u16,
+        charity_id: Option<AccountId>,
+    ) {
+        require!(!name.is_empty() && name.len() <= MAX_GOAL_NAME_LEN, "Invalid savings goal name");
+        require!(penalty_bps <= MAX_BPS, "The penalty can't exceed 100%");
+        require!(
+            (penalty_bps > 0) == charity_id.is_some(),
+            "A penalty requires a charity and the other way round"
+        );
+        let account_id = env::predecessor_account_id();
+        self.token.internal_unwrap_balance_of(&account_id);
+        if let Some(charity_id) = charity_id.as_ref() {
+            self.token.internal_unwrap_balance_of(charity_id);
+        }
+        let mut goals = self.savings_goals.get(&account_id).unwrap_or_default();
+        require!(goals.len() < MAX_GOALS, "Too many savings goals");
+        require!(goals.iter().all(|goal| goal.name != name), "The savings goal already exists");
+
+        let initial_storage_usage = env::storage_usage();
+        goals.push(SavingsGoal {
+            name,
+            balance: U128(0),
+            withdrawal_delay,
+            withdrawal_unlocks_at: U64(0),
+            penalty_bps,
+            charity_id,
+        });
+        self.savings_goals.insert(&account_id, &goals);
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Moves `amount` of the caller's balance into its savings goal `name`.
+    #[payable]
+    pub fn deposit_to_savings(&mut self, name: String, amount: U128) -> U128 {
+        assert_one_yocto();
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        let mut goals = self.internal_unwrap_savings_goals(&account_id);
+        let goal = internal_find_goal(&mut goals, &name);
+        goal.balance = U128(goal.balance.0 + amount.0);
+        let balance = goal.balance;
+        self.savings_goals.insert(&account_id, &goals);
+        self.internal_hold(&account_id, amount.0, "savings");
+        balance
+    }
+
+    /// Starts the withdrawal delay of the caller's savings goal `name`.
+    #[payable]
+    pub fn request_savings_withdrawal(&mut self, name: String) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut goals = self.internal_unwrap_savings_goals(&account_id);
+        let goal = internal_find_goal(&mut goals, &name);
+        goal.withdrawal_unlocks_at = U64(env::block_timestamp() + goal.withdrawal_delay.0);
+        self.savings_goals.insert(&account_id, &goals);
+    }
+
+    /// Returns `amount` of the caller's savings goal `name` to its balance. Before the withdrawal
+    /// delay has passed, the goal's penalty is deducted and sent to its charity. Returns the
+    /// amount received by the caller.
+    #[payable]
+    pub fn withdraw_from_savings(&mut self, name: String, amount: U128) -> U128 {
+        assert_one_yocto();
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        let mut goals = self.internal_unwrap_savings_goals(&account_id);
+        let goal = internal_find_goal(&mut goals, &name);
+        require!(amount.0 <= goal.balance.0, "The amount exceeds the savings balance");
+        let penalty: Balance = if goal.is_unlocked() {
+            0
+        } else {
+            require!(goal.penalty_bps > 0, "The withdrawal delay hasn't passed yet");
+            pro_rata(amount.0, goal.penalty_bps.into(), MAX_BPS.into())
+        };
+        goal.balance = U128(goal.balance.0 - amount.0);
+        goal.withdrawal_unlocks_at = U64(0);
+        let charity_id = goal.charity_id.clone();
+        self.savings_goals.insert(&account_id, &goals);
+
+        let received = amount.0 - penalty;
+        if received > 0 {
+            self.internal_release(&account_id, received, "savings");
+        }
+        if let (Some(charity_id), true) = (charity_id.as_ref(), penalty > 0) {
+            self.internal_release(charity_id, penalty, "savings penalty");
+        }
+        FtSavingsWithdrawn {
+            account_id: &account_id,
+            name: &name,
+            amount: &U128(received),
+            penalty: &U128(penalty),
+        }
+        .emit();
+        received.into()
+    }
+
+    /// Removes the caller's empty savings goal `name` and refunds its storage.
+    #[payable]
+    pub fn close_savings_goal(&mut self, name: String) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut goals = self.internal_unwrap_savings_goals(&account_id);
+        require!(
+            internal_find_goal(&mut goals, &name).balance.0 == 0,
+            "Withdraw the savings before closing the goal"
+        );
+        let initial_storage_usage = env::storage_usage();
+        goals.retain(|goal| goal.name != name);
+        if goals.is_empty() {
+            self.savings_goals.remove(&account_id);
+        } else {
+            self.savings_goals.insert(&account_id, &goals);
+        }
+        refund_released_storage(account_id, initial_storage_usage - env::storage_usage());
+    }
+
+    pub fn savings_goals_of(&self, account_id: AccountId) -> Vec<SavingsGoal> {
+        self.savings_goals.get(&account_id).unwrap_or_default()
+    }
+
+    /// Returns the total amount `account_id` keeps in its savings goals.
+    pub fn savings_balance_of(&self, account_id: AccountId) -> U128 {
+        self.savings_goals_of(account_id).iter().map(|goal| goal.balance.0).sum::<Balance>().into()
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_savings_goals(&self, account_id: &AccountId) -> Vec<SavingsGoal> {
+        self.savings_goals
+            .get(account_id)
+            .unwrap_or_else(|| env::panic_str("The savings goal doesn't exist"))
+    }
+}
+
+fn internal_find_goal<'a>(goals: &'a mut [SavingsGoal], name: &str) -> &'a mut SavingsGoal {
+    goals
+        .iter_mut()
+        .find(|goal| goal.name == name)
+        .unwrap_or_else(|| env::panic_str("The savings goal doesn't exist"))
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    const DELAY: u64 = 1_000;
+
+    /// Saves 1000 of the tokens of accounts(2) in a "bike" goal with a 10% penalty going to
+    /// accounts(1).
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.create_savings_goal("bike".to_string(), DELAY.into(), 1_000, Some(accounts(1)));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.deposit_to_savings("bike".to_string(), 1000.into());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_early_withdrawal_pays_penalty() {
+        let (_, mut contract) = setup();
+        assert_eq!(contract.withdraw_from_savings("bike".to_string(), 500.into()).0, 450);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 50);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 550);
+        assert_eq!(contract.savings_balance_of(accounts(2)).0, 500);
+    }
+
+    #[test]
+    fn test_withdrawal_after_delay() {
+        let (mut context, mut contract) = setup();
+        contract.request_savings_withdrawal("bike".to_string());
+        testing_env!(context.block_timestamp(DELAY).build());
+        assert_eq!(contract.withdraw_from_savings("bike".to_string(), 1000.into()).0, 1000);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        contract.close_savings_goal("bike".to_string());
+        assert!(contract.savings_goals_of(accounts(2)).is_empty());
+    }
+}
@@ -0,0 +1,141 @@
+//! Regulator clawback of frozen funds.
+//!
+//! An account with the compliance role can force-move tokens out of a frozen account to a
+//! recovery account. From a partially frozen account, only the frozen amount can be seized, and
+//! the seized tokens count against it. Every seizure needs a memo explaining it, emits an
+//! `ft_seize` event and is appended to an on-chain audit log. The caller's attached deposit pays
+//! for the log entry.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::events::FtSeize;
+use crate::roles::{require_role, Role};
+use crate::utils::{assert_stored_memo, refund_deposit};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of seizures returned by one `seizures` call.
+const MAX_SEIZURES_PAGE: u64 = 100;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Seizure {
+    pub from: AccountId,
+    pub to: AccountId,
+    pub amount: U128,
+    pub memo: String,
+    /// Account that performed the seizure.
+    pub seized_by: AccountId,
+    /// Block timestamp (in nanoseconds) of the seizure.
+    pub seized_at: U64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Moves `amount` from the frozen account `from` to `to`, regardless of the freeze.
+    #[payable]
+    pub fn seize(&mut self, from: AccountId, to: AccountId, amount: U128, memo: String) {
+        require_role!(self, Role::Compliance);
+        let frozen = self
+            .frozen_accounts
+            .get(&from)
+            .unwrap_or_else(|| env::panic_str("Only frozen accounts can be seized"));
+        require!(!memo.trim().is_empty(), "A seizure requires a memo");
+        assert_stored_memo(&Some(memo.clone()));
+        require!(!self.blacklist.contains(&to), "The receiver is blacklisted");
+        let initial_storage_usage = env::storage_usage();
+        if let Some(frozen) = frozen {
+            require!(amount.0 <= frozen, "The amount exceeds the account's frozen amount");
+            self.frozen_accounts.insert(&from, &Some(frozen - amount.0));
+        }
+        self.token.internal_transfer(&from, &to, amount.into(), Some(memo.clone()));
+        self.internal_record_activity(&from, &to);
+
+        let seized_by = env::predecessor_account_id();
+        FtSeize { from: &from, to: &to, amount: &amount, memo: &memo, seized_by: &seized_by }
+            .emit();
+        self.seizures.push(&Seizure {
+            from,
+            to,
+            amount,
+            memo,
+            seized_by,
+            seized_at: env::block_timestamp().into(),
+        });
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+    }
+
+    /// Returns up to `limit` entries of the seizure audit log, oldest first, starting at
+    /// `from_index`.
+    pub fn seizures(&self, from_index: Option<u64>, limit: Option<u64>) -> Vec<Seizure> {
+        let from_index = from_index.unwrap_or(0);
+        let limit = limit.unwrap_or(MAX_SEIZURES_PAGE).min(MAX_SEIZURES_PAGE);
+        (from_index..self.seizures.len().min(from_index.saturating_add(limit)))
+            .filter_map(|index| self.seizures.get(index))
+            .collect()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Registers accounts(1), sends it some tokens and freezes it.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.freeze_account(accounts(1), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_seize() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.seize(accounts(1), accounts(2), 60.into(), "court order 42".to_string());
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 40);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 40);
+
+        let log = contract.seizures(None, None);
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].from, accounts(1));
+        assert_eq!(log[0].memo, "court order 42");
+        assert!(contract.seizures(Some(1), None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "A seizure requires a memo")]
+    fn test_seize_without_memo() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.seize(accounts(1), accounts(2), 60.into(), " ".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only frozen accounts can be seized")]
+    fn test_seize_unfrozen_account() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.unfreeze_account(accounts(1));
+        contract.seize(accounts(1), accounts(2), 60.into(), "court order 42".to_string());
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the account's frozen amount")]
+    fn test_seize_above_frozen_amount() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.storage_usage(env::storage_usage()).build());
+        contract.freeze_account(accounts(1), Some(50.into()));
+        contract.seize(accounts(1), accounts(2), 30.into(), "court order 42".to_string());
+        contract.seize(accounts(1), accounts(2), 30.into(), "court order 43".to_string());
+    }
+}
@@ -0,0 +1,315 @@
+//! Sponsored onboarding: a relayer pays the storage of a brand-new account and sends it its first
+//! tokens in a single call, so the user never needs NEAR to start holding the token.
+//!
+//! dApps can also prepay storage for their users by registering as a storage sponsor bound to a
+//! referral tag. Registrations made with that tag are paid from the dApp's balance and counted
+//! towards its usage report. When such an account unregisters, its registration deposit goes back
+//! to the sponsor's balance rather than to the account, so registering and unregistering in a loop
+//! can't drain the sponsor.
+
+use near_contract_standards::storage_management::{StorageBalance, StorageManagement};
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::U128;
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, log, near_bindgen, require, AccountId, Balance, Promise};
+
+use crate::events::FtSponsoredTransfer;
+use crate::pause::Feature;
+use crate::{Contract, ContractExt};
+
+/// Maximum length of the attribution tag attached to a sponsored transfer or a referral tag.
+const MAX_CAMPAIGN_LEN: usize = 64;
+
+/// A dApp that prepaid NEAR to cover the storage of accounts registering with its referral tag.
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct StorageSponsor {
+    pub sponsor_id: AccountId,
+    /// NEAR still available to pay for registrations.
+    pub balance: U128,
+    /// NEAR spent on registrations so far.
+    pub spent: U128,
+    /// Number of accounts registered with this tag.
+    pub registrations: u64,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Registers `receiver_id` and transfers `amount` of the caller's tokens to it. The attached
+    /// deposit pays for the receiver's storage and the unused part is refunded to the sponsor.
+    /// The transfer goes through the same checks as `ft_transfer`, but can't be queued, so it
+    /// must stay within the sponsor's soft limit. `campaign` is an optional attribution tag that
+    /// is only used in the emitted event.
+    #[payable]
+    pub fn ft_sponsored_transfer(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        memo: Option<String>,
+        campaign: Option<String>,
+    ) {
+        self.assert_not_paused(Feature::Storage);
+        let sponsor_id = env::predecessor_account_id();
+        require!(
+            !self.token.accounts.contains_key(&receiver_id),
+            "The receiver is already registered"
+        );
+        if let Some(campaign) = campaign.as_ref() {
+            require!(campaign.len() <= MAX_CAMPAIGN_LEN, "The campaign tag is too long");
+        }
+        let storage_cost: Balance = self.token.storage_balance_bounds().min.into();
+        let deposit = env::attached_deposit();
+        require!(
+            deposit >= storage_cost,
+            "The attached deposit is less than the minimum storage balance"
+        );
+
+        require!(
+            !self.internal_exceeds_soft_limit(&sponsor_id, amount.into()),
+            "The amount exceeds the soft limit, use ft_transfer to queue it for approval"
+        );
+
+        self.token.internal_register_account(&receiver_id);
+        let received = self
+            .internal_start_transfer(&sponsor_id, &receiver_id, amount.into(), &memo)
+            .unwrap_or_else(|_| env::panic_str("The receiver only accepts confirmed transfers"));
+        self.token.internal_transfer(&sponsor_id, &receiver_id, received, memo.clone());
+        self.internal_finish_transfer(&sponsor_id, &receiver_id, received, memo);
+
+        let refund = deposit - storage_cost;
+        if refund > 0 {
+            Promise::new(sponsor_id.clone()).transfer(refund);
+        }
+        FtSponsoredTransfer {
+            sponsor_id: &sponsor_id,
+            receiver_id: &receiver_id,
+            amount: &U128(received),
+            storage_deposit: &U128(storage_cost),
+            campaign: campaign.as_deref(),
+        }
+        .emit();
+    }
+
+    /// Binds the referral `tag` to the caller. The attached deposit pays for storing the sponsor
+    /// record and the remainder becomes the sponsor's prepaid balance.
+    #[payable]
+    pub fn register_storage_sponsor(&mut self, tag: String) -> StorageSponsor {
+        require!(!tag.is_empty() && tag.len() <= MAX_CAMPAIGN_LEN, "Invalid referral tag");
+        require!(!self.storage_sponsors.contains_key(&tag), "The referral tag is already taken");
+        let initial_storage_usage = env::storage_usage();
+        let mut sponsor = StorageSponsor {
+            sponsor_id: env::predecessor_account_id(),
+            balance: U128(0),
+            spent: U128(0),
+            registrations: 0,
+        };
+        self.storage_sponsors.insert(&tag, &sponsor);
+        let storage_cost =
+            Balance::from(env::storage_usage() - initial_storage_usage) * env::storage_byte_cost();
+        let deposit = env::attached_deposit();
+        require!(deposit >= storage_cost, "The attached deposit doesn't cover the sponsor record");
+        sponsor.balance = U128(deposit - storage_cost);
+        self.storage_sponsors.insert(&tag, &sponsor);
+        sponsor
+    }
+
+    /// Tops up the prepaid balance of the sponsor bound to `tag` with the attached deposit.
+    #[payable]
+    pub fn fund_storage_sponsor(&mut self, tag: String) -> StorageSponsor {
+        let mut sponsor = self.internal_unwrap_storage_sponsor(&tag);
+        sponsor.balance = U128(sponsor.balance.0 + env::attached_deposit());
+        self.storage_sponsors.insert(&tag, &sponsor);
+        sponsor
+    }
+
+    /// Returns `amount` (or the whole remaining balance) of the prepaid NEAR to the sponsor.
+    #[payable]
+    pub fn withdraw_storage_sponsor(
+        &mut self,
+        tag: String,
+        amount: Option<U128>,
+    ) -> StorageSponsor {
+        assert_one_yocto();
+        let mut sponsor = self.internal_unwrap_storage_sponsor(&tag);
+        require!(
+            env::predecessor_account_id() == sponsor.sponsor_id,
+            "Only the sponsor can withdraw its balance"
+        );
+        let amount = amount.map(|a| a.0).unwrap_or(sponsor.balance.0);
+        require!(amount <= sponsor.balance.0, "The amount is greater than the sponsor balance");
+        sponsor.balance = U128(sponsor.balance.0 - amount);
+        self.storage_sponsors.insert(&tag, &sponsor);
+        if amount > 0 {
+            Promise::new(sponsor.sponsor_id.clone()).transfer(amount);
+        }
+        sponsor
+    }
+
+    /// Registers `account_id` (the caller by default) with the storage paid by the sponsor bound
+    /// to `tag`. Only the account itself or the sponsor may use the tag, so a third party can't
+    /// drain a sponsor by registering arbitrary accounts.
+    pub fn storage_deposit_with_tag(
+        &mut self,
+        account_id: Option<AccountId>,
+        tag: String,
+    ) -> StorageBalance {
+        self.assert_not_paused(Feature::Storage);
+        let predecessor_account_id = env::predecessor_account_id();
+        let account_id = account_id.unwrap_or_else(|| predecessor_account_id.clone());
+        let mut sponsor = self.internal_unwrap_storage_sponsor(&tag);
+        require!(
+            predecessor_account_id == account_id || predecessor_account_id == sponsor.sponsor_id,
+            "Only the account or the sponsor can register with a referral tag"
+        );
+        require!(
+            !self.token.accounts.contains_key(&account_id),
+            "The account is already registered"
+        );
+        self.token.internal_register_account(&account_id);
+        let initial_storage_usage = env::storage_usage();
+        self.sponsored_accounts.insert(&account_id, &tag);
+        let storage_cost = Balance::from(self.token.storage_balance_bounds().min)
+            + Balance::from(env::storage_usage() - initial_storage_usage)
+                * env::storage_byte_cost();
+        require!(sponsor.balance.0 >= storage_cost, "The sponsor balance is too low");
+        sponsor.balance = U128(sponsor.balance.0 - storage_cost);
+        sponsor.spent = U128(sponsor.spent.0 + storage_cost);
+        sponsor.registrations += 1;
+        self.storage_sponsors.insert(&tag, &sponsor);
+        log!("Registered @{} with referral tag {}", account_id, tag);
+        self.token.storage_balance_of(account_id).unwrap()
+    }
+
+    /// Returns the sponsor bound to `tag` along with its usage so far.
+    pub fn storage_sponsor(&self, tag: String) -> Option<StorageSponsor> {
+        self.storage_sponsors.get(&tag)
+    }
+}
+
+impl Contract {
+    /// If a sponsor paid the registration of `account_id`, credits the registration deposit and
+    /// the storage of the sponsorship record back to the sponsor's balance and returns true.
+    pub(crate) fn internal_refund_sponsor(&mut self, account_id: &AccountId) -> bool {
+        let initial_storage_usage = env::storage_usage();
+        let tag = match self.sponsored_accounts.remove(account_id) {
+            Some(tag) => tag,
+            None => return false,
+        };
+        let mut sponsor = self.internal_unwrap_storage_sponsor(&tag);
+        let refund = Balance::from(self.token.storage_balance_bounds().min)
+            + Balance::from(initial_storage_usage - env::storage_usage())
+                * env::storage_byte_cost();
+        sponsor.balance = U128(sponsor.balance.0 + refund);
+        self.storage_sponsors.insert(&tag, &sponsor);
+        true
+    }
+
+    fn internal_unwrap_storage_sponsor(&self, tag: &String) -> StorageSponsor {
+        self.storage_sponsors.get(tag).unwrap_or_else(|| env::panic_str("Unknown referral tag"))
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, get_logs};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    #[test]
+    fn test_sponsored_transfer() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(storage_cost + 10)
+            .build());
+        contract.ft_sponsored_transfer(accounts(3), 100.into(), None, Some("launch".to_string()));
+
+        assert_eq!(contract.ft_balance_of(accounts(3)).0, 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+        let logs = get_logs();
+        assert!(logs.last().unwrap().contains(r#""event":"ft_sponsored_transfer""#));
+        assert!(logs.last().unwrap().contains(r#""campaign":"launch""#));
+    }
+
+    #[test]
+    #[should_panic(expected = "The receiver is already registered")]
+    fn test_sponsored_transfer_to_registered_account() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(storage_cost).build());
+        contract.ft_sponsored_transfer(accounts(2), 100.into(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the soft limit")]
+    fn test_sponsored_transfer_above_soft_limit() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.set_transfer_policy(100.into(), accounts(4));
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(storage_cost).build());
+        contract.ft_sponsored_transfer(accounts(3), 500.into(), None, None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The storage feature is paused")]
+    fn test_sponsored_transfer_while_storage_paused() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.set_feature_paused(Feature::Storage, true);
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context.attached_deposit(storage_cost).build());
+        contract.ft_sponsored_transfer(accounts(3), 100.into(), None, None);
+    }
+
+    #[test]
+    fn test_storage_deposit_with_tag() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(10 * storage_cost)
+            .build());
+        let funded = contract.register_storage_sponsor("dapp".to_string()).balance.0;
+
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .predecessor_account_id(accounts(3))
+            .attached_deposit(0)
+            .build());
+        contract.storage_deposit_with_tag(None, "dapp".to_string());
+
+        assert!(contract.storage_balance_of(accounts(3)).is_some());
+        let sponsor = contract.storage_sponsor("dapp".to_string()).unwrap();
+        assert_eq!(sponsor.registrations, 1);
+        // The sponsor also pays for recording the sponsorship.
+        assert!(sponsor.spent.0 > storage_cost);
+        assert_eq!(sponsor.balance.0, funded - sponsor.spent.0);
+
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.storage_unregister(None);
+        let sponsor = contract.storage_sponsor("dapp".to_string()).unwrap();
+        assert_eq!(sponsor.balance.0, funded);
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the account or the sponsor can register with a referral tag")]
+    fn test_storage_deposit_with_tag_by_third_party() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        let storage_cost = contract.storage_balance_bounds().min.0;
+        testing_env!(context
+            .predecessor_account_id(accounts(4))
+            .attached_deposit(10 * storage_cost)
+            .build());
+        contract.register_storage_sponsor("dapp".to_string());
+
+        testing_env!(context.predecessor_account_id(accounts(1)).attached_deposit(0).build());
+        contract.storage_deposit_with_tag(Some(accounts(3)), "dapp".to_string());
+    }
+}
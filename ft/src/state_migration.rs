@@ -0,0 +1,109 @@
+//! Migration of the contract state after a code upgrade.
+//!
+//! The version of the state layout is stored under its own key, next to the `Contract` struct,
+//! so it can be read before the state is deserialized. The state of the original token contract
+//! has no version: it holds only the token and its metadata, and no owner. `migrate` moves it to
+//! the current layout with an owner and every other feature empty. A change of the layout bumps
+//! `STATE_VERSION` and teaches `migrate` to read the layout it replaces.
+
+use near_contract_standards::fungible_token::metadata::FungibleTokenMetadata;
+use near_contract_standards::fungible_token::FungibleToken;
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::collections::LazyOption;
+use near_sdk::{env, near_bindgen, require, AccountId};
+
+use crate::{Contract, ContractExt};
+
+/// Key of the state layout version.
+const STATE_VERSION_KEY: &[u8] = b"sv";
+/// Version of the current state layout.
+const STATE_VERSION: u8 = 1;
+
+/// State of the original token contract, before the layout was versioned.
+#[derive(BorshDeserialize, BorshSerialize)]
+struct ContractV0 {
+    token: FungibleToken,
+    metadata: LazyOption<FungibleTokenMetadata>,
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Moves the state of the original token contract to the current layout after a code
+    /// upgrade, keeping the balances and the metadata, and makes `owner_id` the owner. Can only
+    /// be called by the contract account itself, e.g. in the batch that deploys the new code.
+    #[private]
+    #[init(ignore_state)]
+    pub fn migrate(owner_id: AccountId) -> Self {
+        let version = state_version();
+        require!(version < STATE_VERSION, "The state is already up to date");
+        let ContractV0 { token, metadata } =
+            env::state_read().unwrap_or_else(|| env::panic_str("The contract is not initialized"));
+        Self::internal_new(token, metadata, owner_id, None)
+    }
+
+    /// Returns the version of the state layout.
+    pub fn state_version(&self) -> u8 {
+        state_version()
+    }
+}
+
+/// Returns the stored version of the state layout, 0 for the state of the original contract.
+fn state_version() -> u8 {
+    env::storage_read(STATE_VERSION_KEY).map_or(0, |version| version[0])
+}
+
+pub(crate) fn write_state_version() {
+    env::storage_write(STATE_VERSION_KEY, &[STATE_VERSION]);
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::fungible_token::metadata::{
+        FungibleTokenMetadataProvider, FT_METADATA_SPEC,
+    };
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::raw_state::{METADATA_PREFIX, TOKEN_PREFIX};
+    use crate::tests::{get_context, setup_contract};
+
+    /// Writes the state of the original contract, with 1000 tokens owned by accounts(1).
+    fn setup_v0() {
+        testing_env!(get_context(accounts(0)).build());
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 24,
+        };
+        let mut token = FungibleToken::new(TOKEN_PREFIX);
+        token.internal_register_account(&accounts(1));
+        token.internal_deposit(&accounts(1), 1_000);
+        let metadata = LazyOption::new(METADATA_PREFIX, Some(&metadata));
+        env::state_write(&ContractV0 { token, metadata });
+    }
+
+    #[test]
+    fn test_migrate_from_v0() {
+        setup_v0();
+        let contract = Contract::migrate(accounts(2));
+        assert_eq!(contract.state_version(), STATE_VERSION);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 1_000);
+        assert_eq!(contract.ft_total_supply().0, 1_000);
+        assert_eq!(contract.ft_metadata().symbol, "TKN");
+        assert_eq!(contract.owner_id, accounts(2));
+        assert!(contract.token.accounts.contains_key(&accounts(2)));
+    }
+
+    #[test]
+    #[should_panic(expected = "The state is already up to date")]
+    fn test_migrate_current_state() {
+        let _ = setup_contract(&[]);
+        Contract::migrate(accounts(2));
+    }
+}
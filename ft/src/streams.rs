@@ -0,0 +1,313 @@
+//! Continuous payment streams.
+//!
+//! A sender escrows an amount that streams to a receiver second by second between a start and an
+//! end time. The receiver withdraws what has streamed so far with `withdraw_from_stream`. Either
+//! party can cancel the stream, which pays the receiver what has streamed and not been withdrawn
+//! yet and returns the rest to the sender. The sender pays for storing the stream and gets the
+//! storage refunded once the stream is fully withdrawn or cancelled.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::{FtStreamCancelled, FtStreamCreated, FtStreamWithdrawn};
+use crate::utils::{pro_rata, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of streams an account can send or receive at once.
+const MAX_STREAMS: usize = 50;
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct Stream {
+    pub stream_id: u64,
+    pub sender_id: AccountId,
+    pub receiver_id: AccountId,
+    pub amount: U128,
+    /// Amount already withdrawn by the receiver.
+    pub withdrawn: U128,
+    /// Block timestamps (in nanoseconds) between which the amount streams.
+    pub start: U64,
+    pub end: U64,
+}
+
+impl Stream {
+    /// Amount streamed by `now`, counted in whole seconds since the start.
+    fn streamed_at(&self, now: u64) -> Balance {
+        let (start, end) = (self.start.0, self.end.0);
+        if now >= end {
+            return self.amount.0;
+        }
+        let elapsed = now.saturating_sub(start) / NANOS_PER_SECOND * NANOS_PER_SECOND;
+        pro_rata(self.amount.0, elapsed, end - start)
+    }
+
+    fn withdrawable_at(&self, now: u64) -> Balance {
+        self.streamed_at(now) - self.withdrawn.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Escrows `amount` of the caller's tokens to stream to `receiver_id` between `start` and
+    /// `end`, and returns the id of the stream. The attached deposit pays for storing the stream.
+    #[payable]
+    pub fn create_stream(
+        &mut self,
+        receiver_id: AccountId,
+        amount: U128,
+        start: U64,
+        end: U64,
+    ) -> u64 {
+        require!(amount.0 > 0, "The amount should be a positive number");
+        require!(start.0 < end.0, "The stream must start before it ends");
+        require!(start.0 >= env::block_timestamp(), "The stream can't start in the past");
+        let sender_id = env::predecessor_account_id();
+        require!(sender_id != receiver_id, "Sender and receiver should be different");
+        require!(
+            !self.internal_exceeds_soft_limit(&sender_id, amount.into()),
+            "The amount exceeds the soft limit, use ft_transfer to queue it for approval"
+        );
+        self.token.internal_unwrap_balance_of(&receiver_id);
+        let mut sent = self.streams_by_sender.get(&sender_id).unwrap_or_default();
+        let mut received = self.streams_by_receiver.get(&receiver_id).unwrap_or_default();
+        require!(
+            sent.len() < MAX_STREAMS && received.len() < MAX_STREAMS,
+            "Too many streams for the sender or the receiver"
+        );
+
+        let initial_storage_usage = env::storage_usage();
+        let amount = U128(self.internal_charge_fee(&sender_id, &receiver_id, amount.0));
+        self.internal_hold(&sender_id, amount.0, "stream");
+        let stream_id = self.next_stream_id;
+        self.next_stream_id += 1;
+        sent.push(stream_id);
+        received.push(stream_id);
+        self.streams_by_sender.insert(&sender_id, &sent);
+        self.streams_by_receiver.insert(&receiver_id, &received);
+        self.streams.insert(
+            &stream_id,
+            &Stream {
+                stream_id,
+                sender_id: sender_id.clone(),
+                receiver_id: receiver_id.clone(),
+                amount,
+                withdrawn: U128(0),
+                start,
+                end,
+            },
+        );
+        FtStreamCreated {
+            stream_id,
+            sender_id: &sender_id,
+            receiver_id: &receiver_id,
+            amount: &amount,
+            start,
+            end,
+        }
+        .emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        stream_id
+    }
+
+    /// Pays the receiver of `stream_id` what has streamed to it so far and returns the amount.
+    #[payable]
+    pub fn withdraw_from_stream(&mut self, stream_id: u64) -> U128 {
+        assert_one_yocto();
+        let mut stream = self.internal_unwrap_stream(stream_id);
+        require!(
+            env::predecessor_account_id() == stream.receiver_id,
+            "Only the receiver can withdraw from the stream"
+        );
+        let amount = stream.withdrawable_at(env::block_timestamp());
+        require!(amount > 0, "Nothing to withdraw yet");
+        stream.withdrawn = U128(stream.withdrawn.0 + amount);
+        self.internal_release(&stream.receiver_id, amount, "stream");
+        FtStreamWithdrawn { stream_id, receiver_id: &stream.receiver_id, amount: &U128(amount) }
+            .emit();
+        if stream.withdrawn == stream.amount {
+            self.internal_remove_stream(stream);
+        } else {
+            self.streams.insert(&stream_id, &stream);
+        }
+        amount.into()
+    }
+
+    /// Ends `stream_id` now: the receiver gets what has streamed and the sender the rest.
+    #[payable]
+    pub fn cancel_stream(&mut self, stream_id: u64) {
+        assert_one_yocto();
+        let stream = self.internal_unwrap_stream(stream_id);
+        let account_id = env::predecessor_account_id();
+        require!(
+            account_id == stream.sender_id || account_id == stream.receiver_id,
+            "Only the sender or the receiver can cancel the stream"
+        );
+        let now = env::block_timestamp();
+        let receiver_amount = stream.withdrawable_at(now);
+        let sender_amount = stream.amount.0 - stream.streamed_at(now);
+        for (account_id, amount) in
+            [(&stream.receiver_id, receiver_amount), (&stream.sender_id, sender_amount)]
+        {
+            if amount == 0 {
+                continue;
+            }
+            if self.token.accounts.contains_key(account_id) {
+                self.internal_release(account_id, amount, "cancelled stream");
+            } else {
+                self.internal_park_refund(account_id.clone(), amount);
+            }
+        }
+        FtStreamCancelled {
+            stream_id,
+            cancelled_by: &account_id,
+            receiver_amount: &U128(receiver_amount),
+            sender_amount: &U128(sender_amount),
+        }
+        .emit();
+        self.internal_remove_stream(stream);
+    }
+
+    pub fn stream(&self, stream_id: u64) -> Option<Stream> {
+        self.streams.get(&stream_id)
+    }
+
+    /// Returns the amount the receiver of `stream_id` can withdraw right now.
+    pub fn withdrawable_from_stream(&self, stream_id: u64) -> U128 {
+        self.internal_unwrap_stream(stream_id).withdrawable_at(env::block_timestamp()).into()
+    }
+
+    pub fn streams_by_sender(
+        &self,
+        sender_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<Stream> {
+        self.internal_streams_page(self.streams_by_sender.get(&sender_id), from_index, limit)
+    }
+
+    pub fn streams_by_receiver(
+        &self,
+        receiver_id: AccountId,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<Stream> {
+        self.internal_streams_page(self.streams_by_receiver.get(&receiver_id), from_index, limit)
+    }
+}
+
+impl Contract {
+    fn internal_unwrap_stream(&self, stream_id: u64) -> Stream {
+        self.streams.get(&stream_id).unwrap_or_else(|| env::panic_str("The stream doesn't exist"))
+    }
+
+    fn internal_streams_page(
+        &self,
+        stream_ids: Option<Vec<u64>>,
+        from_index: Option<u32>,
+        limit: Option<u32>,
+    ) -> Vec<Stream> {
+        stream_ids
+            .unwrap_or_default()
+            .into_iter()
+            .skip(from_index.unwrap_or(0) as usize)
+            .take(limit.map_or(MAX_STREAMS, |limit| limit as usize))
+            .filter_map(|stream_id| self.streams.get(&stream_id))
+            .collect()
+    }
+
+    /// Removes a settled stream from the indexes and refunds its storage to the sender.
+    fn internal_remove_stream(&mut self, stream: Stream) {
+        let initial_storage_usage = env::storage_usage();
+        self.streams.remove(&stream.stream_id);
+        for (index, account_id) in [
+            (&mut self.streams_by_sender, &stream.sender_id),
+            (&mut self.streams_by_receiver, &stream.receiver_id),
+        ] {
+            let mut stream_ids = index.get(account_id).unwrap_or_default();
+            stream_ids.retain(|&stream_id| stream_id != stream.stream_id);
+            if stream_ids.is_empty() {
+                index.remove(account_id);
+            } else {
+                index.insert(account_id, &stream_ids);
+            }
+        }
+        refund_released_storage(
+            stream.sender_id,
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Streams 1000 tokens from accounts(2) to accounts(1) over 100 seconds.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.create_stream(accounts(1), 1000.into(), 0.into(), (100 * NANOS_PER_SECOND).into());
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_withdraw_from_stream() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .block_timestamp(30 * NANOS_PER_SECOND + 1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        assert_eq!(contract.withdraw_from_stream(0).0, 300);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 300);
+        assert_eq!(contract.streams_by_receiver(accounts(1), None, None)[0].withdrawn.0, 300);
+    }
+
+    #[test]
+    fn test_cancel_stream() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(40 * NANOS_PER_SECOND).build());
+        contract.cancel_stream(0);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 400);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 400);
+        assert!(contract.stream(0).is_none());
+        assert!(contract.streams_by_sender(accounts(2), None, None).is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Only the receiver can withdraw from the stream")]
+    fn test_withdraw_by_sender() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(40 * NANOS_PER_SECOND).build());
+        contract.withdraw_from_stream(0);
+    }
+
+    #[test]
+    #[should_panic(expected = "The stream can't start in the past")]
+    fn test_backdated_stream() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .block_timestamp(NANOS_PER_SECOND)
+            .attached_deposit(env::storage_byte_cost() * 1000)
+            .build());
+        contract.create_stream(accounts(1), 1000.into(), 0.into(), (2 * NANOS_PER_SECOND).into());
+    }
+
+    #[test]
+    #[should_panic(expected = "The amount exceeds the soft limit")]
+    fn test_stream_above_soft_limit() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.set_transfer_policy(100.into(), accounts(3));
+        contract.create_stream(accounts(1), 1000.into(), 0.into(), NANOS_PER_SECOND.into());
+    }
+}
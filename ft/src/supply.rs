@@ -0,0 +1,134 @@
+//! Changes of the total supply after deployment.
+//!
+//! Every mint goes through `internal_mint`, which enforces the maximum supply set at
+//! initialization, so a capped token stays capped whatever the minting path.
+
+use near_contract_standards::fungible_token::events::{FtBurn, FtMint};
+use near_sdk::json_types::U128;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::pause::Feature;
+use crate::roles::{require_role, Role};
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Mints `amount` new tokens to `account_id`, which must be registered. Can only be called
+    /// by a minter.
+    #[payable]
+    pub fn ft_mint(&mut self, account_id: AccountId, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        require_role!(self, Role::Minter);
+        self.internal_mint(&account_id, amount.into(), memo.as_deref());
+    }
+
+    /// Returns the total supply the token can never exceed, or `None` if it is not capped.
+    pub fn ft_max_supply(&self) -> Option<U128> {
+        self.max_supply.map(U128)
+    }
+
+    /// Destroys `amount` of the caller's tokens, reducing the total supply.
+    #[payable]
+    pub fn ft_burn(&mut self, amount: U128, memo: Option<String>) {
+        assert_one_yocto();
+        self.assert_not_paused(Feature::Burn);
+        require!(amount.0 > 0, "The amount should be a positive number");
+        let account_id = env::predecessor_account_id();
+        self.internal_assert_unfrozen(&account_id, amount.into());
+        self.internal_assert_unlocked(&account_id, amount.into());
+        self.internal_assert_custody_allows(&account_id, amount.into());
+        self.token.internal_withdraw(&account_id, amount.into());
+        FtBurn { owner_id: &account_id, amount: &amount, memo: memo.as_deref() }.emit();
+        self.on_tokens_burned(account_id, amount.into());
+    }
+}
+
+impl Contract {
+    /// Mints `amount` new tokens to `account_id`, which must be registered.
+    pub(crate) fn internal_mint(
+        &mut self,
+        account_id: &AccountId,
+        amount: Balance,
+        memo: Option<&str>,
+    ) {
+        self.assert_not_paused(Feature::Mint);
+        require!(!self.blacklist.contains(account_id), "The receiver is blacklisted");
+        self.internal_assert_whitelisted(account_id);
+        if let Some(max_supply) = self.max_supply {
+            require!(
+                self.token
+                    .total_supply
+                    .checked_add(amount)
+                    .map_or(false, |supply| supply <= max_supply),
+                "Minting would exceed the maximum supply"
+            );
+        }
+        self.token.internal_deposit(account_id, amount);
+        FtMint { owner_id: account_id, amount: &U128(amount), memo }.emit();
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::fungible_token::metadata::{
+        FungibleTokenMetadata, FT_METADATA_SPEC,
+    };
+    use near_sdk::test_utils::accounts;
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{get_context, setup_contract, TOTAL_SUPPLY};
+
+    #[test]
+    fn test_mint() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_mint(accounts(2), 100.into(), None);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY + 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY + 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "Minting would exceed the maximum supply")]
+    fn test_mint_above_max_supply() {
+        let mut context = get_context(accounts(2));
+        testing_env!(context.build());
+        let metadata = FungibleTokenMetadata {
+            spec: FT_METADATA_SPEC.to_string(),
+            name: "Capped token".to_string(),
+            symbol: "CAP".to_string(),
+            icon: None,
+            reference: None,
+            reference_hash: None,
+            decimals: 24,
+        };
+        let mut contract = Contract::new(
+            accounts(2),
+            TOTAL_SUPPLY.into(),
+            metadata,
+            Some((TOTAL_SUPPLY + 100).into()),
+        );
+        assert_eq!(contract.ft_max_supply(), Some(U128(TOTAL_SUPPLY + 100)));
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_mint(accounts(2), 100.into(), None);
+        contract.ft_mint(accounts(2), 1.into(), None);
+    }
+
+    #[test]
+    fn test_burn() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).build());
+        contract.ft_burn(100.into(), None);
+        assert_eq!(contract.ft_total_supply().0, TOTAL_SUPPLY - 100);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 100);
+    }
+
+    #[test]
+    #[should_panic(expected = "The caller doesn't have the minter role")]
+    fn test_mint_by_non_minter() {
+        let (mut context, mut contract) = setup_contract(&[]);
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.ft_mint(accounts(1), 100.into(), None);
+    }
+}
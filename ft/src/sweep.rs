@@ -0,0 +1,132 @@
+//! Dust consolidation for accounts that are done with the token.
+//!
+//! `sweep_to` moves the caller's whole balance to another account and closes the caller's
+//! registration in one call. It is subject to the same checks as `storage_unregister`, and the
+//! records the account stored are dropped the same way, but their storage is refunded together
+//! with the registration deposit to a beneficiary of the caller's choice.
+
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::{Contract, ContractExt};
+
+#[near_bindgen]
+impl Contract {
+    /// Transfers the caller's whole balance to `receiver_id` and unregisters the caller. The
+    /// storage deposit is refunded to `beneficiary_id`, or to the caller if it is `None`. The
+    /// receiver can't be an account that requires confirmed transfers, since the caller would be
+    /// gone before it could settle them.
+    #[payable]
+    pub fn sweep_to(&mut self, receiver_id: AccountId, beneficiary_id: Option<AccountId>) {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        require!(account_id != receiver_id, "Can't sweep an account into itself");
+        self.internal_assert_can_close(&account_id);
+        require!(
+            !self.transfer_policies.contains_key(&account_id),
+            "Accounts with a transfer policy can't be swept"
+        );
+        require!(
+            !self.transfer_confirmations.contains_key(&receiver_id),
+            "Can't sweep into an account that requires confirmed transfers"
+        );
+        let amount = self.token.internal_unwrap_balance_of(&account_id);
+        if amount > 0 {
+            self.internal_transfer(&account_id, &receiver_id, amount, Some("sweep".to_string()));
+        }
+
+        self.token.accounts.remove(&account_id);
+        self.internal_close_account(
+            &account_id,
+            beneficiary_id.unwrap_or_else(|| account_id.clone()),
+        );
+        self.on_account_closed(account_id, 0);
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_contract_standards::storage_management::StorageManagement;
+    use near_sdk::test_utils::{accounts, get_created_receipts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Registers accounts(1) with a balance of 100.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_contract(&[accounts(1)]);
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        (context, contract)
+    }
+
+    #[test]
+    fn test_sweep_to() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.sweep_to(accounts(2), Some(accounts(3)));
+
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY);
+        assert!(contract.storage_balance_of(accounts(1)).is_none());
+        let refund = get_created_receipts().pop().unwrap();
+        assert_eq!(refund.receiver_id, accounts(3));
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't sweep an account into itself")]
+    fn test_sweep_to_self() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.sweep_to(accounts(1), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The fee collector can't unregister")]
+    fn test_sweep_fee_collector() {
+        let (mut context, mut contract) = setup();
+        contract.set_fee_collector(Some(accounts(1)));
+        testing_env!(context.predecessor_account_id(accounts(1)).build());
+        contract.sweep_to(accounts(2), None);
+    }
+
+    #[test]
+    #[should_panic(expected = "The account is frozen")]
+    fn test_sweep_frozen_account() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.attached_deposit(env::storage_byte_cost() * 1000).build());
+        contract.freeze_account(accounts(1), None);
+        testing_env!(context.attached_deposit(1).predecessor_account_id(accounts(1)).build());
+        contract.sweep_to(accounts(2), None);
+    }
+
+    #[test]
+    fn test_sweep_drops_allowances() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.ft_approve(accounts(3), 50.into(), None);
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.sweep_to(accounts(2), None);
+        assert_eq!(contract.ft_allowance(accounts(1), accounts(3)).0, 0);
+        assert!(contract.allowance_spenders.get(&accounts(1)).is_none());
+    }
+
+    #[test]
+    #[should_panic(expected = "Can't sweep into an account that requires confirmed transfers")]
+    fn test_sweep_to_confirming_receiver() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.set_transfer_confirmation(Some(1_000.into()));
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        contract.sweep_to(accounts(2), None);
+    }
+}
@@ -0,0 +1,76 @@
+//! Argument and return types of the contract's views that are not stored in state.
+//!
+//! Types that are both stored and returned by views live next to the code that stores them.
+//! With the `abi` feature every type in the interface derives `JsonSchema`, so the contract's ABI
+//! can be generated with `scripts/abi.sh` and clients generated from it.
+
+use near_sdk::json_types::{Base64VecU8, U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::AccountId;
+
+/// A storage key of the contract and the raw value stored under it, if any.
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct RawStateRecord {
+    pub key: Base64VecU8,
+    pub value: Option<Base64VecU8>,
+}
+
+/// The answer of `verify_min_balance`.
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct BalanceProof {
+    pub account_id: AccountId,
+    pub min_balance: U128,
+    /// Whether the account held at least `min_balance`.
+    pub holds: bool,
+    /// Block height at which the balance was read.
+    pub snapshot_id: U64,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct AccountActivityView {
+    pub last_sent_at: Option<U64>,
+    pub last_received_at: Option<U64>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct ApprovalFreeze {
+    /// Block timestamp (in nanoseconds) from which the approvals can be unfrozen, if an unfreeze
+    /// was requested.
+    pub unfreezes_at: Option<U64>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct DistributionProgress {
+    pub claimed: U128,
+    /// Tokens neither claimed nor swept yet.
+    pub remaining: U128,
+    pub expired: bool,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct TransferFee {
+    pub fee_bps: u16,
+    pub fee_collector: Option<AccountId>,
+}
+
+#[derive(Serialize)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct AllowanceView {
+    pub owner_id: AccountId,
+    pub spender_id: AccountId,
+    pub amount: U128,
+    pub expires_at: Option<U64>,
+}
@@ -0,0 +1,60 @@
+use near_sdk::{env, require, AccountId, Balance, Promise, StorageUsage};
+
+/// Maximum length of a memo stored with a transfer whose storage is paid by the contract.
+pub(crate) const MAX_STORED_MEMO_LEN: usize = 256;
+
+/// Rejects memos too long to be stored at the contract's expense.
+pub(crate) fn assert_stored_memo(memo: &Option<String>) {
+    if let Some(memo) = memo {
+        require!(memo.len() <= MAX_STORED_MEMO_LEN, "The memo is too long");
+    }
+}
+
+/// Returns `total * part / whole` rounded down, without overflowing for any `part <= whole`.
+pub(crate) fn pro_rata(total: Balance, part: u64, whole: u64) -> Balance {
+    let (part, whole) = (Balance::from(part), Balance::from(whole));
+    // The remainder term stays below `whole²`, so it fits even when `total * part` wouldn't.
+    total / whole * part + total % whole * part / whole
+}
+
+/// Returns about `total * part / whole` for any `part <= whole`, dropping the low bits of `part`
+/// and `whole` that don't fit in a `u64`.
+pub(crate) fn pro_rata_balance(total: Balance, part: Balance, whole: Balance) -> Balance {
+    let shift = 64u32.saturating_sub(whole.leading_zeros());
+    pro_rata(total, (part >> shift) as u64, (whole >> shift) as u64)
+}
+
+/// Charges the caller for `storage_used` bytes out of the attached deposit and refunds the rest.
+pub(crate) fn refund_deposit(storage_used: StorageUsage) {
+    let required_cost = env::storage_byte_cost() * Balance::from(storage_used);
+    let attached_deposit = env::attached_deposit();
+    require!(
+        required_cost <= attached_deposit,
+        format!("Must attach {} yoctoNEAR to cover storage", required_cost)
+    );
+    let refund = attached_deposit - required_cost;
+    if refund > 1 {
+        Promise::new(env::predecessor_account_id()).transfer(refund);
+    }
+}
+
+/// Settles the storage change since `initial_storage_usage`: charges the caller for the growth
+/// out of the attached deposit, or refunds the released storage to `account_id`, and refunds the
+/// rest of the deposit to the caller either way.
+pub(crate) fn refund_storage_change(account_id: AccountId, initial_storage_usage: StorageUsage) {
+    let storage_usage = env::storage_usage();
+    if storage_usage > initial_storage_usage {
+        refund_deposit(storage_usage - initial_storage_usage);
+    } else {
+        refund_deposit(0);
+        refund_released_storage(account_id, initial_storage_usage - storage_usage);
+    }
+}
+
+/// Refunds the storage cost of `storage_released` bytes to `account_id`.
+pub(crate) fn refund_released_storage(account_id: AccountId, storage_released: StorageUsage) {
+    let refund = env::storage_byte_cost() * Balance::from(storage_released);
+    if refund > 0 {
+        Promise::new(account_id).transfer(refund);
+    }
+}
@@ -0,0 +1,296 @@
+//! Vesting schedules with a linear unlock.
+//!
+//! The owner grants a beneficiary a schedule backed by the owner's own tokens, which the contract
+//! holds until they vest. Nothing vests before the cliff; from then on the schedule unlocks
+//! linearly between its start and end, and the beneficiary claims the unlocked part with
+//! `claim_vested`. Since the locked tokens sit in the contract's balance, they never count towards
+//! the beneficiary's transferable balance. The owner pays for storing the schedules and gets the
+//! storage refunded once they are fully claimed.
+//!
+//! Grants created as revocable can be revoked by the owner: the part vested so far stays
+//! claimable and the rest goes to the vesting treasury, which is the owner unless set otherwise.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::json_types::{U128, U64};
+use near_sdk::serde::Serialize;
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId, Balance};
+
+use crate::events::{FtVestingClaimed, FtVestingCreated, FtVestingRevoked};
+use crate::utils::{pro_rata, refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+/// Maximum number of schedules a beneficiary can have at once.
+const MAX_SCHEDULES: usize = 10;
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Clone)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub struct VestingSchedule {
+    pub grant_id: u64,
+    /// Block timestamps (in nanoseconds) bounding the linear unlock.
+    pub start: U64,
+    pub cliff: U64,
+    pub end: U64,
+    pub total: U128,
+    /// Amount already released to the beneficiary.
+    pub claimed: U128,
+    pub revocable: bool,
+}
+
+impl VestingSchedule {
+    /// Amount unlocked at `now`, including the claimed part.
+    fn vested_at(&self, now: u64) -> Balance {
+        let (start, end, total) = (self.start.0, self.end.0, self.total.0);
+        if now < self.cliff.0 {
+            return 0;
+        }
+        if now >= end {
+            return total;
+        }
+        pro_rata(total, now - start, end - start)
+    }
+
+    fn claimable_at(&self, now: u64) -> Balance {
+        self.vested_at(now) - self.claimed.0
+    }
+}
+
+#[near_bindgen]
+impl Contract {
+    /// Locks `total` of the owner's tokens in a new schedule for `account_id` and returns the
+    /// id of the grant. The attached deposit pays for storing the schedule.
+    #[payable]
+    pub fn create_vesting(
+        &mut self,
+        account_id: AccountId,
+        start: U64,
+        cliff: U64,
+        end: U64,
+        total: U128,
+        revocable: bool,
+    ) -> u64 {
+        self.assert_owner();
+        require!(
+            start.0 <= cliff.0 && cliff.0 <= end.0 && start.0 < end.0,
+            "The schedule must satisfy start <= cliff <= end and start < end"
+        );
+        require!(total.0 > 0, "The total should be a positive number");
+        self.token.internal_unwrap_balance_of(&account_id);
+        let mut schedules = self.vestings.get(&account_id).unwrap_or_default();
+        require!(schedules.len() < MAX_SCHEDULES, "Too many vesting schedules for the account");
+
+        let initial_storage_usage = env::storage_usage();
+        let owner_id = self.owner_id.clone();
+        self.internal_hold(&owner_id, total.into(), "vesting");
+        let grant_id = self.next_vesting_grant_id;
+        self.next_vesting_grant_id += 1;
+        schedules.push(VestingSchedule {
+            grant_id,
+            start,
+            cliff,
+            end,
+            total,
+            claimed: U128(0),
+            revocable,
+        });
+        self.vestings.insert(&account_id, &schedules);
+        FtVestingCreated { grant_id, account_id: &account_id, total: &total, start, cliff, end }
+            .emit();
+        refund_deposit(env::storage_usage().saturating_sub(initial_storage_usage));
+        grant_id
+    }
+
+    /// Ends the revocable grant `grant_id` of `account_id` now. The part vested so far stays
+    /// claimable and the rest is returned to the vesting treasury.
+    #[payable]
+    pub fn revoke_vesting(&mut self, account_id: AccountId, grant_id: u64) -> U128 {
+        assert_one_yocto();
+        self.assert_owner();
+        let mut schedules = self.vestings.get(&account_id).unwrap_or_default();
+        let index = schedules
+            .iter()
+            .position(|schedule| schedule.grant_id == grant_id)
+            .unwrap_or_else(|| env::panic_str("The vesting grant doesn't exist"));
+        let schedule = &mut schedules[index];
+        require!(schedule.revocable, "The vesting grant isn't revocable");
+
+        let now = env::block_timestamp();
+        let vested = schedule.vested_at(now);
+        let unvested = schedule.total.0 - vested;
+        // Freeze the schedule at its current state so nothing vests anymore.
+        schedule.total = U128(vested);
+        schedule.end = U64(now.max(schedule.start.0 + 1));
+        schedule.cliff = U64(schedule.cliff.0.min(schedule.end.0));
+        schedule.revocable = false;
+
+        let initial_storage_usage = env::storage_usage();
+        schedules.retain(|schedule| schedule.claimed != schedule.total);
+        if schedules.is_empty() {
+            self.vestings.remove(&account_id);
+        } else {
+            self.vestings.insert(&account_id, &schedules);
+        }
+        let treasury_id = self.vesting_treasury();
+        if unvested > 0 {
+            self.internal_release(&treasury_id, unvested, "revoked vesting");
+        }
+        FtVestingRevoked {
+            grant_id,
+            account_id: &account_id,
+            treasury_id: &treasury_id,
+            amount: &U128(unvested),
+        }
+        .emit();
+        refund_released_storage(
+            self.owner_id.clone(),
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+        unvested.into()
+    }
+
+    /// Sets the account receiving the unvested part of revoked grants, or the owner when `None`.
+    #[payable]
+    pub fn set_vesting_treasury(&mut self, treasury_id: Option<AccountId>) {
+        assert_one_yocto();
+        self.assert_owner();
+        if let Some(treasury_id) = treasury_id.as_ref() {
+            self.token.internal_unwrap_balance_of(treasury_id);
+        }
+        self.vesting_treasury_id = treasury_id;
+    }
+
+    pub fn vesting_treasury(&self) -> AccountId {
+        self.vesting_treasury_id.clone().unwrap_or_else(|| self.owner_id.clone())
+    }
+
+    /// Releases the unlocked part of the caller's schedules and returns the released amount.
+    #[payable]
+    pub fn claim_vested(&mut self) -> U128 {
+        assert_one_yocto();
+        let account_id = env::predecessor_account_id();
+        let mut schedules = self
+            .vestings
+            .get(&account_id)
+            .unwrap_or_else(|| env::panic_str("The account has no vesting schedules"));
+        let now = env::block_timestamp();
+        let mut amount = 0;
+        for schedule in schedules.iter_mut() {
+            let claimable = schedule.claimable_at(now);
+            schedule.claimed = U128(schedule.claimed.0 + claimable);
+            amount += claimable;
+        }
+        require!(amount > 0, "Nothing has vested yet");
+
+        let initial_storage_usage = env::storage_usage();
+        schedules.retain(|schedule| schedule.claimed != schedule.total);
+        if schedules.is_empty() {
+            self.vestings.remove(&account_id);
+        } else {
+            self.vestings.insert(&account_id, &schedules);
+        }
+        self.internal_release(&account_id, amount, "vesting");
+        FtVestingClaimed { account_id: &account_id, amount: &U128(amount) }.emit();
+        refund_released_storage(
+            self.owner_id.clone(),
+            initial_storage_usage.saturating_sub(env::storage_usage()),
+        );
+        amount.into()
+    }
+
+    pub fn vestings_of(&self, account_id: AccountId) -> Vec<VestingSchedule> {
+        self.vestings.get(&account_id).unwrap_or_default()
+    }
+
+    /// Returns the amount `account_id` can claim right now.
+    pub fn vested_balance_of(&self, account_id: AccountId) -> U128 {
+        let now = env::block_timestamp();
+        self.vestings_of(account_id)
+            .iter()
+            .map(|schedule| schedule.claimable_at(now))
+            .sum::<Balance>()
+            .into()
+    }
+
+    /// Returns the amount of `account_id`'s schedules that is still locked.
+    pub fn unvested_balance_of(&self, account_id: AccountId) -> U128 {
+        let now = env::block_timestamp();
+        self.vestings_of(account_id)
+            .iter()
+            .map(|schedule| schedule.total.0 - schedule.vested_at(now))
+            .sum::<Balance>()
+            .into()
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit, TOTAL_SUPPLY};
+
+    /// Grants accounts(1) 1000 tokens vesting from 100 to 1100 with a cliff at 300.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (mut context, mut contract) = setup_contract(&[accounts(1)]);
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.create_vesting(
+            accounts(1),
+            100.into(),
+            300.into(),
+            1100.into(),
+            1000.into(),
+            true,
+        );
+        testing_env!(context
+            .storage_usage(env::storage_usage())
+            .attached_deposit(1)
+            .predecessor_account_id(accounts(1))
+            .build());
+        (context, contract)
+    }
+
+    #[test]
+    fn test_vesting_is_locked() {
+        let (_, contract) = setup();
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 0);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 1000);
+        assert_eq!(contract.unvested_balance_of(accounts(1)).0, 1000);
+    }
+
+    #[test]
+    #[should_panic(expected = "Nothing has vested yet")]
+    fn test_claim_before_cliff() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(299).build());
+        contract.claim_vested();
+    }
+
+    #[test]
+    fn test_claim_vested() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(350).build());
+        assert_eq!(contract.vested_balance_of(accounts(1)).0, 250);
+        assert_eq!(contract.claim_vested().0, 250);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 250);
+        assert_eq!(contract.unvested_balance_of(accounts(1)).0, 750);
+
+        testing_env!(context.block_timestamp(2000).build());
+        assert_eq!(contract.claim_vested().0, 750);
+        assert!(contract.vestings_of(accounts(1)).is_empty());
+    }
+
+    #[test]
+    fn test_revoke_vesting() {
+        let (mut context, mut contract) = setup();
+        testing_env!(context.block_timestamp(350).predecessor_account_id(accounts(2)).build());
+        assert_eq!(contract.revoke_vesting(accounts(1), 0).0, 750);
+        assert_eq!(contract.ft_balance_of(accounts(2)).0, TOTAL_SUPPLY - 250);
+        assert_eq!(contract.unvested_balance_of(accounts(1)).0, 0);
+
+        testing_env!(context.block_timestamp(2000).predecessor_account_id(accounts(1)).build());
+        assert_eq!(contract.claim_vested().0, 250);
+        assert!(contract.vestings_of(accounts(1)).is_empty());
+    }
+}
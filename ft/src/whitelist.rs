@@ -0,0 +1,108 @@
+//! Whitelist-only transfer mode for compliance launches.
+//!
+//! In `WhitelistOnly` mode only whitelisted accounts can receive tokens, whether by transfer or
+//! by mint. Accounts already holding tokens can still send them to whitelisted accounts. The
+//! contract's own account, which holds tokens in escrow, is always allowed. The mode and the
+//! whitelist are managed by accounts with the compliance role.
+
+use near_sdk::borsh::{self, BorshDeserialize, BorshSerialize};
+use near_sdk::serde::{Deserialize, Serialize};
+use near_sdk::{assert_one_yocto, env, near_bindgen, require, AccountId};
+
+use crate::roles::{require_role, Role};
+use crate::utils::{refund_deposit, refund_released_storage};
+use crate::{Contract, ContractExt};
+
+#[derive(BorshDeserialize, BorshSerialize, Serialize, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[cfg_attr(feature = "abi", derive(schemars::JsonSchema))]
+#[serde(crate = "near_sdk::serde")]
+pub enum TransferMode {
+    Open,
+    WhitelistOnly,
+}
+
+#[near_bindgen]
+impl Contract {
+    #[payable]
+    pub fn set_transfer_mode(&mut self, mode: TransferMode) {
+        assert_one_yocto();
+        require_role!(self, Role::Compliance);
+        self.transfer_mode = mode;
+    }
+
+    /// Whitelists `account_id`. The attached deposit pays for storing the entry.
+    #[payable]
+    pub fn add_to_whitelist(&mut self, account_id: AccountId) {
+        require_role!(self, Role::Compliance);
+        let initial_storage_usage = env::storage_usage();
+        require!(self.whitelist.insert(&account_id), "The account is already whitelisted");
+        refund_deposit(env::storage_usage() - initial_storage_usage);
+    }
+
+    #[payable]
+    pub fn remove_from_whitelist(&mut self, account_id: AccountId) {
+        assert_one_yocto();
+        require_role!(self, Role::Compliance);
+        let initial_storage_usage = env::storage_usage();
+        require!(self.whitelist.remove(&account_id), "The account is not whitelisted");
+        refund_released_storage(
+            env::predecessor_account_id(),
+            initial_storage_usage - env::storage_usage(),
+        );
+    }
+
+    pub fn transfer_mode(&self) -> TransferMode {
+        self.transfer_mode
+    }
+
+    pub fn is_whitelisted(&self, account_id: AccountId) -> bool {
+        self.whitelist.contains(&account_id)
+    }
+}
+
+impl Contract {
+    pub(crate) fn internal_assert_whitelisted(&self, receiver_id: &AccountId) {
+        require!(self.internal_is_whitelisted(receiver_id), "The receiver is not whitelisted");
+    }
+
+    /// Returns whether `receiver_id` can receive tokens in the current transfer mode.
+    pub(crate) fn internal_is_whitelisted(&self, receiver_id: &AccountId) -> bool {
+        self.transfer_mode != TransferMode::WhitelistOnly
+            || *receiver_id == env::current_account_id()
+            || self.whitelist.contains(receiver_id)
+    }
+}
+
+#[cfg(all(test, not(target_arch = "wasm32")))]
+mod tests {
+    use near_contract_standards::fungible_token::core::FungibleTokenCore;
+    use near_sdk::test_utils::{accounts, VMContextBuilder};
+    use near_sdk::testing_env;
+
+    use super::*;
+    use crate::tests::{setup_contract, with_storage_deposit};
+
+    /// Registers accounts(1) and switches to the whitelist-only mode.
+    fn setup() -> (VMContextBuilder, Contract) {
+        let (context, mut contract) = setup_contract(&[accounts(1)]);
+        contract.set_transfer_mode(TransferMode::WhitelistOnly);
+        (context, contract)
+    }
+
+    #[test]
+    #[should_panic(expected = "The receiver is not whitelisted")]
+    fn test_transfer_to_non_whitelisted() {
+        let (_, mut contract) = setup();
+        contract.ft_transfer(accounts(1), 100.into(), None);
+    }
+
+    #[test]
+    fn test_transfer_to_whitelisted() {
+        let (mut context, mut contract) = setup();
+        testing_env!(with_storage_deposit(&mut context).build());
+        contract.add_to_whitelist(accounts(1));
+        testing_env!(context.storage_usage(env::storage_usage()).attached_deposit(1).build());
+        contract.ft_transfer(accounts(1), 100.into(), None);
+        assert_eq!(contract.ft_balance_of(accounts(1)).0, 100);
+    }
+}
@@ -0,0 +1,6 @@
+#!/bin/bash
+set -e
+cd "`dirname $0`"/../ft
+cargo near abi
+cd ..
+cp ft/target/near/*_abi.json ./res/